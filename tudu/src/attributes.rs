@@ -0,0 +1,446 @@
+//! The types of TODO attributes, and checking attributes against them.
+//!
+//! The PRD's attributes are built in: `bidir`, `one_way` and `close_on_delete` are flags,
+//! `due` is a date, `labels` is a list, `assignee` is a user, and `status`, `section` and `db`
+//! are text. `attributes.keys` in `.tudu.yaml` adds keys or retypes these. `prop.*` keys set
+//! tracker properties directly, so they're always allowed, and untyped unless configured.
+//!
+//! Keys that aren't known are kept as written. One that looks like a typo of a known key is
+//! reported, and with `attributes.strict`, so is every other one.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
+use crate::parser::DiagnosticKind;
+use crate::{AttributeConfig, AttributeType, AttributesConfig, TodoAttributeValue};
+
+/// A calendar date, as in `due=2025-10-01`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    /// Reads an ISO 8601 date, `YYYY-MM-DD`, checking the day exists.
+    pub fn parse(text: &str) -> Option<Date> {
+        let mut parts = text.split('-');
+        let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() || year.len() != 4 || month.len() != 2 || day.len() != 2 {
+            return None;
+        }
+        if !text.chars().all(|c| c.is_ascii_digit() || c == '-') {
+            return None;
+        }
+
+        let date = Date {
+            year: year.parse().ok()?,
+            month: month.parse().ok()?,
+            day: day.parse().ok()?,
+        };
+        let days_in_month = match date.month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if date.is_leap_year() => 29,
+            2 => 28,
+            _ => return None,
+        };
+        (1..=days_in_month).contains(&date.day).then_some(date)
+    }
+
+    fn is_leap_year(&self) -> bool {
+        (self.year.is_multiple_of(4) && !self.year.is_multiple_of(100))
+            || self.year.is_multiple_of(400)
+    }
+}
+
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// The attribute keys TODOs are checked against: the built-in ones, plus `attributes.keys`.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    keys: BTreeMap<String, AttributeConfig>,
+    strict: bool,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        let keys = [
+            ("bidir", AttributeType::Flag),
+            ("one_way", AttributeType::Flag),
+            ("close_on_delete", AttributeType::Flag),
+            ("due", AttributeType::Date),
+            ("labels", AttributeType::List),
+            ("assignee", AttributeType::User),
+            ("status", AttributeType::Text),
+            ("section", AttributeType::Text),
+            ("db", AttributeType::Text),
+        ]
+        .into_iter()
+        .map(|(key, kind)| {
+            let config = AttributeConfig {
+                kind,
+                values: Vec::new(),
+                required: false,
+            };
+            (key.to_string(), config)
+        })
+        .collect();
+        Self {
+            keys,
+            strict: false,
+        }
+    }
+}
+
+impl Schema {
+    /// The built-in keys, with `config`'s keys added on top.
+    pub fn new(config: &AttributesConfig) -> Result<Self, String> {
+        let mut schema = Self {
+            strict: config.strict,
+            ..Self::default()
+        };
+
+        for (key, attribute) in &config.keys {
+            match attribute.kind {
+                AttributeType::Enum if attribute.values.is_empty() => {
+                    return Err(format!(
+                        "attribute '{}' is an enum, but lists no values",
+                        key
+                    ));
+                }
+                AttributeType::Enum => {}
+                _ if !attribute.values.is_empty() => {
+                    return Err(format!(
+                        "attribute '{}' lists values, but only enums take them",
+                        key
+                    ));
+                }
+                _ => {}
+            }
+            schema.keys.insert(key.clone(), attribute.clone());
+        }
+        Ok(schema)
+    }
+
+    /// Whether `key` is a flag, which is never the next item of a list before it.
+    pub fn is_flag(&self, key: &str) -> bool {
+        self.kind(key) == Some(AttributeType::Flag)
+    }
+
+    /// Whether a bare value right after `key=value` adds to it as a list. Only lists (and keys
+    /// we know nothing about) take more than one value.
+    pub fn takes_list(&self, key: &str) -> bool {
+        matches!(self.kind(key), None | Some(AttributeType::List))
+    }
+
+    fn kind(&self, key: &str) -> Option<AttributeType> {
+        self.keys.get(key).map(|attribute| attribute.kind)
+    }
+
+    /// Types each attribute, and reports the ones that are of the wrong type, unknown or missing.
+    /// `spans` has where each attribute was written; `parens` is where missing ones are reported.
+    pub fn check(
+        &self,
+        attributes: &mut HashMap<String, TodoAttributeValue>,
+        spans: &HashMap<String, Range<usize>>,
+        parens: Range<usize>,
+        diagnostics: &mut Vec<(DiagnosticKind, Range<usize>)>,
+    ) {
+        let mut keys: Vec<String> = attributes.keys().cloned().collect();
+        // By position, so diagnostics come out in the order they're written
+        keys.sort_by_key(|key| spans[key].start);
+
+        for key in keys {
+            let span = spans[&key].clone();
+            let Some(attribute) = self.keys.get(&key) else {
+                if key.starts_with("prop.") {
+                    continue;
+                }
+                let suggestion = self.suggest(&key);
+                if suggestion.is_some() || self.strict {
+                    diagnostics.push((DiagnosticKind::UnknownAttribute { key, suggestion }, span));
+                }
+                continue;
+            };
+
+            let value = attributes.remove(&key).unwrap();
+            match typed(&key, value, attribute) {
+                Ok(value) => {
+                    attributes.insert(key, value);
+                }
+                Err(reason) => diagnostics.push((DiagnosticKind::BadAttribute(reason), span)),
+            }
+        }
+
+        for (key, attribute) in &self.keys {
+            if attribute.required && !spans.contains_key(key) {
+                diagnostics.push((
+                    DiagnosticKind::MissingAttribute(key.clone()),
+                    parens.clone(),
+                ));
+            }
+        }
+    }
+
+    /// The known key closest to `key`, if it's close enough to be what was meant.
+    fn suggest(&self, key: &str) -> Option<String> {
+        closest(key, self.keys.keys().map(String::as_str))
+    }
+}
+
+/// The candidate closest to `text`, if it's within a typo or two of it.
+fn closest<'a>(text: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let max_distance = (text.chars().count() / 3).max(1);
+    candidates
+        .map(|candidate| (strsim::damerau_levenshtein(text, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Turns a value as parsed (a flag, text or a list) into the type `attribute` says it is.
+fn typed(
+    key: &str,
+    value: TodoAttributeValue,
+    attribute: &AttributeConfig,
+) -> Result<TodoAttributeValue, String> {
+    if attribute.kind == AttributeType::Flag {
+        return match value {
+            TodoAttributeValue::Flag(_) => Ok(value),
+            TodoAttributeValue::Text(text) if text == "true" => Ok(TodoAttributeValue::Flag(true)),
+            TodoAttributeValue::Text(text) if text == "false" => {
+                Ok(TodoAttributeValue::Flag(false))
+            }
+            _ => Err(format!(
+                "'{}' is a flag; write {} or {}=false",
+                key, key, key
+            )),
+        };
+    }
+
+    let text = match value {
+        TodoAttributeValue::Text(text) => text,
+        TodoAttributeValue::List(values) if attribute.kind == AttributeType::List => {
+            return Ok(TodoAttributeValue::List(values));
+        }
+        TodoAttributeValue::List(_) => return Err(format!("'{}' takes one value", key)),
+        _ => return Err(format!("'{}' needs a value, like {}=...", key, key)),
+    };
+
+    match attribute.kind {
+        AttributeType::Text => Ok(TodoAttributeValue::Text(text)),
+        AttributeType::List => Ok(TodoAttributeValue::List(vec![text])),
+        AttributeType::Date => Date::parse(&text)
+            .map(TodoAttributeValue::Date)
+            .ok_or_else(|| format!("'{}' is a date, like 2025-10-01, not '{}'", key, text)),
+        AttributeType::Integer => text
+            .parse()
+            .map(TodoAttributeValue::Number)
+            .map_err(|_| format!("'{}' is a whole number, not '{}'", key, text)),
+        AttributeType::User => {
+            let user = text.strip_prefix('@').unwrap_or(&text);
+            if user.is_empty() || user.contains(char::is_whitespace) {
+                Err(format!(
+                    "'{}' is a user, like alice or @alice, not '{}'",
+                    key, text
+                ))
+            } else {
+                Ok(TodoAttributeValue::Text(user.to_string()))
+            }
+        }
+        AttributeType::Enum if attribute.values.contains(&text) => {
+            Ok(TodoAttributeValue::Text(text))
+        }
+        AttributeType::Enum => {
+            let mut reason = format!(
+                "'{}' is one of {}, not '{}'",
+                key,
+                attribute.values.join(", "),
+                text
+            );
+            if let Some(suggestion) = closest(&text, attribute.values.iter().map(String::as_str)) {
+                reason.push_str(&format!("; did you mean '{}'?", suggestion));
+            }
+            Err(reason)
+        }
+        AttributeType::Flag => unreachable!("flags are handled above"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configured(yaml: &str) -> Schema {
+        let config: AttributesConfig = serde_yaml::from_str(yaml).unwrap();
+        Schema::new(&config).unwrap()
+    }
+
+    fn text(value: &str) -> TodoAttributeValue {
+        TodoAttributeValue::Text(value.to_string())
+    }
+
+    /// Checks one attribute, returning its typed value or what's wrong with it.
+    fn check(
+        schema: &Schema,
+        key: &str,
+        value: TodoAttributeValue,
+    ) -> Result<TodoAttributeValue, String> {
+        let mut attributes = HashMap::from([(key.to_string(), value)]);
+        let spans = HashMap::from([(key.to_string(), 0..1)]);
+        let mut diagnostics = Vec::new();
+        schema.check(&mut attributes, &spans, 0..1, &mut diagnostics);
+        match diagnostics.pop() {
+            Some((kind, _)) => Err(kind.to_string()),
+            None => Ok(attributes.remove(key).unwrap()),
+        }
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(
+            Date::parse("2024-02-29"),
+            Some(Date {
+                year: 2024,
+                month: 2,
+                day: 29
+            })
+        );
+        assert_eq!(Date::parse("2024-02-29").unwrap().to_string(), "2024-02-29");
+        for bad in [
+            "2025-02-29",
+            "2025-13-01",
+            "2025-04-31",
+            "2025-1-01",
+            "25-01-01",
+            "2025-01-01-01",
+            "+025-01-01",
+            "soon",
+        ] {
+            assert_eq!(Date::parse(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn built_in_types() {
+        let schema = Schema::default();
+        assert_eq!(
+            check(&schema, "bidir", text("false")),
+            Ok(TodoAttributeValue::Flag(false))
+        );
+        assert_eq!(
+            check(&schema, "due", text("2025-10-01")),
+            Ok(TodoAttributeValue::Date(Date {
+                year: 2025,
+                month: 10,
+                day: 1
+            }))
+        );
+        assert_eq!(
+            check(&schema, "labels", text("net")),
+            Ok(TodoAttributeValue::List(vec!["net".to_string()]))
+        );
+        assert_eq!(
+            check(&schema, "assignee", text("@alice")),
+            Ok(text("alice"))
+        );
+        // Unknown keys are kept as written
+        assert_eq!(
+            check(&schema, "wip", TodoAttributeValue::Flag(true)),
+            Ok(TodoAttributeValue::Flag(true))
+        );
+    }
+
+    #[test]
+    fn wrong_types() {
+        let schema = Schema::default();
+        assert_eq!(
+            check(&schema, "bidir", text("yes")),
+            Err("bad attribute: 'bidir' is a flag; write bidir or bidir=false".to_string())
+        );
+        assert_eq!(
+            check(&schema, "due", text("2025-02-30")),
+            Err("bad attribute: 'due' is a date, like 2025-10-01, not '2025-02-30'".to_string())
+        );
+        assert_eq!(
+            check(&schema, "assignee", TodoAttributeValue::Flag(true)),
+            Err("bad attribute: 'assignee' needs a value, like assignee=...".to_string())
+        );
+    }
+
+    #[test]
+    fn configured_types() {
+        let schema = configured(
+            "keys:
+  priority: { type: enum, values: [low, medium, high] }
+  estimate: { type: integer }
+  due: { type: text }",
+        );
+        assert_eq!(check(&schema, "priority", text("low")), Ok(text("low")));
+        assert_eq!(
+            check(&schema, "priority", text("hgih")),
+            Err("bad attribute: 'priority' is one of low, medium, high, not 'hgih'; did you mean 'high'?".to_string())
+        );
+        assert_eq!(
+            check(&schema, "estimate", text("3")),
+            Ok(TodoAttributeValue::Number(3))
+        );
+        assert!(check(&schema, "estimate", text("three")).is_err());
+        // Built-in keys can be retyped
+        assert_eq!(check(&schema, "due", text("soon")), Ok(text("soon")));
+    }
+
+    #[test]
+    fn bad_config() {
+        for yaml in [
+            "keys: { priority: { type: enum } }",
+            "keys: { estimate: { type: integer, values: [1, 2] } }",
+        ] {
+            let config: AttributesConfig = serde_yaml::from_str(yaml).unwrap();
+            assert!(Schema::new(&config).is_err(), "{}", yaml);
+        }
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let schema = Schema::default();
+        assert_eq!(
+            check(&schema, "asignee", text("alice")),
+            Err("unknown attribute 'asignee'; did you mean 'assignee'?".to_string())
+        );
+        assert_eq!(
+            check(&schema, "lables", text("net")),
+            Err("unknown attribute 'lables'; did you mean 'labels'?".to_string())
+        );
+        assert!(check(&schema, "wip", TodoAttributeValue::Flag(true)).is_ok());
+        assert!(check(&schema, "prop.Status", text("Done")).is_ok());
+
+        let strict = configured("strict: true");
+        assert_eq!(
+            check(&strict, "wip", TodoAttributeValue::Flag(true)),
+            Err("unknown attribute 'wip'".to_string())
+        );
+        assert!(check(&strict, "prop.Status", text("Done")).is_ok());
+    }
+
+    #[test]
+    fn required_keys() {
+        let schema = configured("keys: { priority: { type: text, required: true } }");
+        let mut attributes = HashMap::new();
+        let mut diagnostics = Vec::new();
+        schema.check(&mut attributes, &HashMap::new(), 4..10, &mut diagnostics);
+        assert_eq!(
+            diagnostics,
+            vec![(
+                DiagnosticKind::MissingAttribute("priority".to_string()),
+                4..10
+            )]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! The canonical form of a TODO, which `tudu fmt` rewrites TODOs into:
+//!
+//! ```text
+//! MARKER[(owner, ref, ..., flag, ..., key=value, ...)]: text
+//! ```
+//!
+//! The owner comes first, then the references in the order they're written, then bare flags
+//! and then every other attribute, each sorted by key. Flags go before values so that none of
+//! them can be read back as the last item of a list.
+
+use crate::attributes::Schema;
+use crate::ids::IdPatterns;
+use crate::{TodoAttributeValue, parser};
+
+/// The canonical form of the TODO line `text`, which starts with a marker `marker_len` bytes
+/// long. `None` if the TODO is malformed, or if rewriting it would lose something, like the
+/// `some words` in `TODO(some words): ...`; those are left for a person to fix.
+pub fn canonicalize(
+    text: &str,
+    marker_len: usize,
+    ids: &IdPatterns,
+    schema: &Schema,
+) -> Option<String> {
+    let parsed = parser::parse(text, marker_len, ids, schema);
+    if !parsed.diagnostics.is_empty() {
+        return None;
+    }
+
+    let mut items: Vec<String> = parsed.owner.iter().cloned().collect();
+    items.extend(
+        parsed
+            .references
+            .iter()
+            .map(|reference| reference.to_string()),
+    );
+    if let Some(attributes) = &parsed.attributes {
+        let mut keys: Vec<&String> = attributes.keys().collect();
+        keys.sort_by_key(|key| (attributes[*key] != TodoAttributeValue::Flag(true), *key));
+        items.extend(
+            keys.into_iter()
+                .map(|key| attribute(key, &attributes[key], schema)),
+        );
+    }
+
+    let marker = text.get(..marker_len)?;
+    let mut canonical = if items.is_empty() {
+        format!("{}:", marker)
+    } else {
+        format!("{}({}):", marker, items.join(", "))
+    };
+    if !parsed.message.is_empty() {
+        canonical.push(' ');
+        canonical.push_str(parsed.message);
+    }
+
+    // Never drop anything: every item in the parentheses has to be written back, and the
+    // canonical form has to read back the same
+    let reparsed = parser::parse(&canonical, marker_len, ids, schema);
+    // A legacy `TODO <ID>:` has the one item, outside of any parentheses
+    let items_in = |text: &str, parsed: &parser::ParsedTodo| match &parsed.reference_span {
+        Some(span) if text[span.clone()].starts_with('(') => {
+            parser::paren_items(&text[span.clone()])
+        }
+        _ => parsed.references.len(),
+    };
+    let same = items_in(text, &parsed) == items_in(&canonical, &reparsed)
+        && reparsed.diagnostics.is_empty()
+        && reparsed.owner == parsed.owner
+        && reparsed.references == parsed.references
+        && reparsed.attributes == parsed.attributes
+        && reparsed.message == parsed.message;
+    same.then_some(canonical)
+}
+
+/// Writes one attribute: `bidir`, `bidir=false`, or `key=value`.
+fn attribute(key: &str, value: &TodoAttributeValue, schema: &Schema) -> String {
+    match value {
+        TodoAttributeValue::Flag(true) => key.to_string(),
+        // A list item that's also a flag's name has to be quoted, or it'd end the list
+        TodoAttributeValue::List(values) => {
+            let values: Vec<_> = values
+                .iter()
+                .map(|value| {
+                    if schema.is_flag(value) {
+                        parser::quote(value)
+                    } else {
+                        parser::quote_if_needed(value).into_owned()
+                    }
+                })
+                .collect();
+            format!("{}={}", key, values.join(","))
+        }
+        value => format!("{}={}", key, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(text: &str) -> Option<String> {
+        canonicalize(
+            text,
+            "TODO".len(),
+            &IdPatterns::default(),
+            &Schema::default(),
+        )
+    }
+
+    #[test]
+    fn already_canonical() {
+        for text in [
+            "TODO: plain",
+            "TODO(TASK-1): tracked",
+            "TODO(alice, TASK-1, bidir, labels=a,b): everything",
+            "TODO:",
+        ] {
+            assert_eq!(canonical(text).as_deref(), Some(text));
+        }
+    }
+
+    #[test]
+    fn rewrites() {
+        let cases = [
+            ("TODO JIRA-1: legacy", "TODO(JIRA-1): legacy"),
+            ("TODO - dashed", "TODO: dashed"),
+            ("TODO no colon", "TODO: no colon"),
+            ("TODO:tight", "TODO: tight"),
+            ("TODO(TASK-1,bidir): x", "TODO(TASK-1, bidir): x"),
+            ("TODO(@alice): x", "TODO(alice): x"),
+            (
+                "TODO(TASK-1, status = \"In Progress\", wip, labels=b, bidir=true, due=2025-10-01): x",
+                "TODO(TASK-1, bidir, wip, due=2025-10-01, labels=b, status=\"In Progress\"): x",
+            ),
+            (
+                "TODO(new=\"Add retries\", one_way=false): x",
+                "TODO(new=\"Add retries\", one_way=false): x",
+            ),
+            // Flags go first, where they can't be taken for a list's values
+            (
+                "TODO(TASK-1, labels=a, b, bidir): x",
+                "TODO(TASK-1, bidir, labels=a,b): x",
+            ),
+            // List items named like a flag stay list items
+            (
+                r#"TODO(TASK-1, labels="bidir",x): x"#,
+                r#"TODO(TASK-1, labels="bidir",x): x"#,
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(canonical(text).as_deref(), Some(expected), "{}", text);
+        }
+    }
+
+    #[test]
+    fn leaves_what_it_cant_rewrite_alone() {
+        for text in [
+            "TODO(): empty",
+            "TODO(TASK-1: unclosed",
+            "TODO(some words): not an owner or an ID",
+            "TODO(some words, TASK-1): x",
+            "TODO(TASK-1, bidir, bidir): duplicate",
+        ] {
+            assert_eq!(canonical(text), None, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Turning the bytes of a file into text we can scan.
+//!
+//! A byte order mark always wins. Otherwise we try UTF-8, then each encoding listed under
+//! `scan.encodings` in `.tudu.yaml`, in order. Files with NUL bytes near the start are treated
+//! as binary, unless a UTF-16 encoding is configured and decodes them.
+//!
+//! Files are rewritten in the encoding they were read in; see [`Source`].
+
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+
+/// How much of a file to look at when deciding whether it's binary.
+const SNIFF_LEN: usize = 8 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum Decoded {
+    Text(String),
+    Binary,
+    /// Text, but not in UTF-8 or any of the configured encodings
+    Unknown,
+}
+
+/// A file's text as it is on disk, line endings and all, for rewriting TODOs in place. Offsets
+/// into it are the same as into the scanned text, which only has its line endings normalized
+/// and its byte order mark left out.
+#[derive(Debug)]
+pub struct Source {
+    pub text: String,
+    encoding: &'static Encoding,
+    /// The byte order mark the file started with, which isn't part of `text`
+    bom: Vec<u8>,
+    /// Whether decoding replaced bytes that weren't valid, so they can't be written back
+    lossy: bool,
+}
+
+impl Source {
+    pub fn encoding(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    /// `text` in the encoding the file was read in, byte order mark included. `None` if the
+    /// file couldn't be decoded exactly, or the text now has characters the encoding doesn't.
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        if self.lossy {
+            return None;
+        }
+        let mut bytes = self.bom.clone();
+        // encoding_rs only decodes UTF-16; it encodes it as UTF-8
+        if self.encoding == UTF_16LE {
+            bytes.extend(self.text.encode_utf16().flat_map(u16::to_le_bytes));
+        } else if self.encoding == UTF_16BE {
+            bytes.extend(self.text.encode_utf16().flat_map(u16::to_be_bytes));
+        } else {
+            let (encoded, _, unmappable) = self.encoding.encode(&self.text);
+            if unmappable {
+                return None;
+            }
+            bytes.extend_from_slice(&encoded);
+        }
+        Some(bytes)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Decoder {
+    fallbacks: Vec<&'static Encoding>,
+}
+
+impl Decoder {
+    /// A decoder that falls back to the encodings with these labels, e.g. `latin1` or `utf-16le`.
+    pub fn new(labels: &[String]) -> Result<Self, String> {
+        let fallbacks = labels
+            .iter()
+            .map(|label| {
+                Encoding::for_label(label.trim().as_bytes())
+                    .ok_or_else(|| format!("unknown encoding '{}' in scan.encodings", label))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { fallbacks })
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Decoded {
+        match self.decode_source(bytes) {
+            Ok(source) => Decoded::Text(normalize_line_endings(source.text)),
+            Err(decoded) => decoded,
+        }
+    }
+
+    /// Decodes a file the way [`decode`](Self::decode) does, but keeps its line endings and
+    /// what it takes to write it back. Files that aren't text are `Err`, as they're decoded.
+    pub fn decode_source(&self, bytes: &[u8]) -> Result<Source, Decoded> {
+        if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+            let (text, lossy) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+            return Ok(Source {
+                text: text.into_owned(),
+                encoding,
+                bom: bytes[..bom_len].to_vec(),
+                lossy,
+            });
+        }
+
+        let binary = is_binary(bytes);
+        if !binary && let Ok(text) = std::str::from_utf8(bytes) {
+            return Ok(Source {
+                text: text.to_string(),
+                encoding: UTF_8,
+                bom: Vec::new(),
+                lossy: false,
+            });
+        }
+
+        for &encoding in &self.fallbacks {
+            // NUL bytes are expected in UTF-16, but mean binary for anything else
+            let wide = encoding == UTF_16LE || encoding == UTF_16BE;
+            if binary && !wide {
+                continue;
+            }
+            if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(bytes)
+            {
+                return Ok(Source {
+                    text: text.into_owned(),
+                    encoding,
+                    bom: Vec::new(),
+                    lossy: false,
+                });
+            }
+        }
+
+        if binary {
+            Err(Decoded::Binary)
+        } else {
+            Err(Decoded::Unknown)
+        }
+    }
+}
+
+/// Whether a file looks binary: like git, we call anything with a NUL byte near the start binary.
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(SNIFF_LEN)].contains(&0)
+}
+
+/// Turns lone `\r` line endings (classic Mac) into `\n`, so everything downstream only has to
+/// split on `\n`. `\r\n` is left alone, and since both are one byte, offsets don't move.
+fn normalize_line_endings(text: String) -> String {
+    if !text.contains('\r') {
+        return text;
+    }
+
+    let mut bytes = text.into_bytes();
+    for i in 0..bytes.len() {
+        if bytes[i] == b'\r' && bytes.get(i + 1) != Some(&b'\n') {
+            bytes[i] = b'\n';
+        }
+    }
+    // Only ASCII bytes were swapped for other ASCII bytes
+    String::from_utf8(bytes).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoder(labels: &[&str]) -> Decoder {
+        let labels: Vec<String> = labels.iter().map(|l| l.to_string()).collect();
+        Decoder::new(&labels).unwrap()
+    }
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn utf8() {
+        let decoded = Decoder::default().decode("caf\u{e9} // TODO".as_bytes());
+        assert_eq!(decoded, Decoded::Text("caf\u{e9} // TODO".to_string()));
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        let mut bytes = b"\xEF\xBB\xBF".to_vec();
+        bytes.extend_from_slice(b"// TODO");
+        assert_eq!(
+            Decoder::default().decode(&bytes),
+            Decoded::Text("// TODO".to_string())
+        );
+
+        // UTF-16 is full of NUL bytes, but the BOM says it's text
+        let mut bytes = b"\xFF\xFE".to_vec();
+        bytes.extend(utf16le("// TODO: r\u{e9}sum\u{e9}"));
+        assert_eq!(
+            Decoder::default().decode(&bytes),
+            Decoded::Text("// TODO: r\u{e9}sum\u{e9}".to_string())
+        );
+    }
+
+    #[test]
+    fn fallback_encodings() {
+        let latin1 = b"# caf\xE9\n# TODO";
+        assert_eq!(Decoder::default().decode(latin1), Decoded::Unknown);
+        assert_eq!(
+            decoder(&["latin1"]).decode(latin1),
+            Decoded::Text("# caf\u{e9}\n# TODO".to_string())
+        );
+
+        let bytes = utf16le("// TODO");
+        assert_eq!(Decoder::default().decode(&bytes), Decoded::Binary);
+        assert_eq!(decoder(&["latin1"]).decode(&bytes), Decoded::Binary);
+        assert_eq!(
+            decoder(&["latin1", "utf-16le"]).decode(&bytes),
+            Decoded::Text("// TODO".to_string())
+        );
+    }
+
+    #[test]
+    fn sources_are_written_back_as_they_were_read() {
+        let decoder = decoder(&["windows-1252", "utf-16le"]);
+        let mut utf16_with_bom = b"\xFF\xFE".to_vec();
+        utf16_with_bom.extend(utf16le("// TODO: r\u{e9}sum\u{e9}\r"));
+        for bytes in [
+            b"\xEF\xBB\xBF// TODO\r\n".to_vec(),
+            b"# caf\xE9\r# TODO".to_vec(),
+            utf16_with_bom,
+            utf16le("// TODO"),
+        ] {
+            let source = decoder.decode_source(&bytes).unwrap();
+            assert_eq!(source.encode().as_deref(), Some(bytes.as_slice()));
+        }
+
+        let mut source = decoder.decode_source(b"# caf\xE9").unwrap();
+        assert_eq!(source.encoding(), "windows-1252");
+        source.text.push_str(" \u{2603}");
+        assert_eq!(source.encode(), None);
+
+        // A bad byte after a BOM is replaced, and so can't be written back
+        let source = decoder.decode_source(b"\xEF\xBB\xBF\xFF").unwrap();
+        assert_eq!(source.encode(), None);
+    }
+
+    #[test]
+    fn unknown_encoding_label() {
+        let labels = vec!["klingon".to_string()];
+        assert!(Decoder::new(&labels).is_err());
+    }
+
+    #[test]
+    fn binary() {
+        let decoder = Decoder::default();
+        assert_eq!(
+            decoder.decode(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Decoded::Binary
+        );
+        assert_eq!(decoder.decode(b""), Decoded::Text(String::new()));
+
+        // Only the start is sniffed
+        let mut bytes = vec![b'a'; SNIFF_LEN];
+        bytes.push(0);
+        assert!(!is_binary(&bytes));
+    }
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(
+            normalize_line_endings("a\r\nb\rc\n".to_string()),
+            "a\r\nb\nc\n"
+        );
+        assert_eq!(normalize_line_endings("a\r".to_string()), "a\n");
+    }
+}
@@ -0,0 +1,105 @@
+//! Errors from a `tudu` run, and the exit codes they map to.
+//!
+//! Errors about a single file (it can't be read, it isn't text) don't stop a scan: they're
+//! collected and reported as warnings, and the rest of the tree is still scanned.
+
+use std::path::PathBuf;
+
+/// Exit codes of the `tudu` binary.
+pub mod exit_code {
+    /// The scan finished, and no TODO met the `--fail-on` threshold
+    pub const SUCCESS: i32 = 0;
+    /// At least one TODO met the `--fail-on` threshold, or in validate mode, a TODO is malformed
+    pub const FINDINGS: i32 = 1;
+    /// `.tudu.yaml` (or a command-line option) is invalid
+    pub const CONFIG_ERROR: i32 = 2;
+    /// A configured issue provider couldn't be set up or queried in sync mode, where it's needed
+    /// to file issues. Checking references only warns.
+    pub const PROVIDER_ERROR: i32 = 3;
+    /// The path to scan, or the config file, couldn't be read
+    pub const IO_ERROR: i32 = 4;
+}
+
+#[derive(Debug)]
+pub enum TuduError {
+    Config(String),
+    Provider(String),
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A TODO that couldn't be rewritten in place, e.g. to add the ID of an issue just filed
+    Rewrite {
+        path: PathBuf,
+        reason: String,
+    },
+    /// A file that looked like text, but isn't UTF-8 or any encoding in `scan.encodings`
+    UnknownEncoding {
+        path: PathBuf,
+    },
+}
+
+impl TuduError {
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        TuduError::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// The file this error is about, if any.
+    pub fn path(&self) -> Option<&PathBuf> {
+        match self {
+            TuduError::Io { path, .. }
+            | TuduError::Rewrite { path, .. }
+            | TuduError::UnknownEncoding { path } => Some(path),
+            TuduError::Config(_) | TuduError::Provider(_) => None,
+        }
+    }
+
+    /// What the process should exit with when this error stops a run.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TuduError::Config(_) => exit_code::CONFIG_ERROR,
+            TuduError::Provider(_) => exit_code::PROVIDER_ERROR,
+            TuduError::Io { .. }
+            | TuduError::Rewrite { .. }
+            | TuduError::UnknownEncoding { .. } => exit_code::IO_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Display for TuduError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TuduError::Config(message) => write!(f, "Invalid configuration: {}", message),
+            TuduError::Provider(message) => write!(f, "Provider error: {}", message),
+            TuduError::Io { path, source } => match source.kind() {
+                std::io::ErrorKind::NotFound => write!(f, "'{}' not found", path.display()),
+                std::io::ErrorKind::PermissionDenied => {
+                    write!(f, "Permission denied reading '{}'", path.display())
+                }
+                _ => write!(f, "Error reading '{}': {}", path.display(), source),
+            },
+            TuduError::Rewrite { path, reason } => {
+                write!(f, "Couldn't rewrite '{}': {}", path.display(), reason)
+            }
+            TuduError::UnknownEncoding { path } => {
+                write!(
+                    f,
+                    "'{}' is not UTF-8 or any encoding in scan.encodings",
+                    path.display()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TuduError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TuduError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Recognizing shorthand issue IDs, like `TASK-123`, `P2P-14` or `#123`, and which of the
+//! configured providers each one belongs to.
+//!
+//! Every provider has an `id_pattern`, a regex the whole ID has to match. Jira's defaults to
+//! its project key (`PROJ-\d+`), GitHub's and GitLab's to `#\d+`. A Notion database without one
+//! gets the generic `[A-Z][A-Z0-9_]*-\d+`, which is also what's used with no providers at all.
+//! Providers are tried by name, and the generic pattern last.
+
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use crate::{ProviderConfig, TodoReference};
+
+/// What an ID looks like when nothing more specific is configured.
+const DEFAULT_PATTERN: &str = r"[A-Z][A-Z0-9_]*-\d+";
+
+#[derive(Debug, Clone)]
+pub struct IdPatterns {
+    patterns: Vec<IdPattern>,
+}
+
+#[derive(Debug, Clone)]
+struct IdPattern {
+    regex: Regex,
+    /// The provider's name in `.tudu.yaml`, or `None` for the default pattern without one
+    provider: Option<String>,
+    target: Target,
+}
+
+/// Where a matching ID points, which is everything but the ID itself.
+#[derive(Debug, Clone)]
+enum Target {
+    Notion,
+    Jira,
+    GitHub { repo: String },
+    GitLab { project: String },
+}
+
+impl Default for IdPatterns {
+    fn default() -> Self {
+        Self {
+            patterns: vec![IdPattern {
+                regex: anchored(DEFAULT_PATTERN).unwrap(),
+                provider: None,
+                target: Target::Notion,
+            }],
+        }
+    }
+}
+
+impl IdPatterns {
+    /// Compiles each provider's `id_pattern`, or its default.
+    pub fn new(providers: &BTreeMap<String, ProviderConfig>) -> Result<Self, String> {
+        let mut patterns = Vec::new();
+        // The first Notion database without a pattern of its own, which gets the default one
+        let mut fallback = None;
+
+        for (name, config) in providers {
+            let (pattern, target) = match config {
+                ProviderConfig::Notion(notion) => match &notion.id_pattern {
+                    Some(pattern) => (pattern.clone(), Target::Notion),
+                    None => {
+                        fallback.get_or_insert_with(|| name.clone());
+                        continue;
+                    }
+                },
+                ProviderConfig::Jira(jira) => (
+                    jira.id_pattern
+                        .clone()
+                        .unwrap_or_else(|| format!(r"{}-\d+", regex::escape(&jira.project))),
+                    Target::Jira,
+                ),
+                ProviderConfig::Github(github) => (
+                    github
+                        .id_pattern
+                        .clone()
+                        .unwrap_or_else(|| r"#\d+".to_string()),
+                    Target::GitHub {
+                        repo: format!("{}/{}", github.owner, github.repo),
+                    },
+                ),
+                ProviderConfig::Gitlab(gitlab) => (
+                    gitlab
+                        .id_pattern
+                        .clone()
+                        .unwrap_or_else(|| r"#\d+".to_string()),
+                    Target::GitLab {
+                        project: gitlab.project.clone(),
+                    },
+                ),
+            };
+            let regex = anchored(&pattern)
+                .map_err(|err| format!("provider '{}' has an invalid id_pattern: {}", name, err))?;
+            patterns.push(IdPattern {
+                regex,
+                provider: Some(name.clone()),
+                target,
+            });
+        }
+
+        if fallback.is_some() || providers.is_empty() {
+            patterns.push(IdPattern {
+                regex: anchored(DEFAULT_PATTERN).unwrap(),
+                provider: fallback,
+                target: Target::Notion,
+            });
+        }
+        Ok(Self { patterns })
+    }
+
+    /// The reference a shorthand ID stands for, if it's an ID at all: `P2P-14` becomes a Jira
+    /// key if it matches the Jira provider's pattern, and `#123` an issue in its GitHub repo.
+    pub fn resolve(&self, id: &str) -> Option<TodoReference> {
+        self.patterns
+            .iter()
+            .filter(|pattern| pattern.regex.is_match(id))
+            .find_map(|pattern| pattern.target.reference(id))
+    }
+
+    /// The name of the provider a shorthand ID belongs to, for telling databases of the same
+    /// kind apart.
+    pub fn provider_for(&self, id: &str) -> Option<&str> {
+        self.patterns
+            .iter()
+            .find(|pattern| pattern.regex.is_match(id) && pattern.target.reference(id).is_some())
+            .and_then(|pattern| pattern.provider.as_deref())
+    }
+}
+
+impl Target {
+    fn reference(&self, id: &str) -> Option<TodoReference> {
+        match self {
+            Target::Notion => Some(TodoReference::Tracked(id.to_string())),
+            Target::Jira => Some(TodoReference::Jira {
+                key: id.to_string(),
+            }),
+            Target::GitHub { repo } => Some(TodoReference::GitHub {
+                repo: repo.clone(),
+                number: trailing_number(id)?,
+            }),
+            Target::GitLab { project } => Some(TodoReference::GitLab {
+                project: project.clone(),
+                number: trailing_number(id)?,
+            }),
+        }
+    }
+}
+
+/// The issue number at the end of an ID, as in `#123` or `GH-123`.
+fn trailing_number(id: &str) -> Option<u64> {
+    let digits = id.len() - id.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    id[id.len() - digits..].parse().ok().filter(|&n| n > 0)
+}
+
+/// Patterns match the whole ID, whether or not they're written with `^` and `$`.
+fn anchored(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{})$", pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(yaml: &str) -> IdPatterns {
+        let providers = serde_yaml::from_str(yaml).unwrap();
+        IdPatterns::new(&providers).unwrap()
+    }
+
+    #[test]
+    fn test_valid_ids() {
+        let ids = IdPatterns::default();
+        let valid_ids = vec!["TASK-1", "BUG-123", "FEATURE-4567", "P2P-14"];
+        for id in valid_ids {
+            assert!(ids.resolve(id).is_some(), "Expected '{}' to be valid", id);
+        }
+    }
+
+    #[test]
+    fn test_invalid_ids() {
+        let ids = IdPatterns::default();
+        let invalid_ids = vec!["task-1", "BUG123", "FEATURE_", "123-ABC", "BUG-", "#12"];
+        for id in invalid_ids {
+            assert!(ids.resolve(id).is_none(), "Expected '{}' to be invalid", id);
+        }
+    }
+
+    #[test]
+    fn routing() {
+        let ids = patterns(
+            r#"
+jira: { type: jira, server: "https://example.atlassian.net", project: P2P }
+github: { type: github, owner: org, repo: app }
+linear: { type: notion, database_id: abc, id_pattern: "[a-z]+-\\d+" }
+"#,
+        );
+        assert_eq!(
+            ids.resolve("P2P-14"),
+            Some(TodoReference::Jira {
+                key: "P2P-14".to_string()
+            })
+        );
+        assert_eq!(
+            ids.resolve("#123"),
+            Some(TodoReference::GitHub {
+                repo: "org/app".to_string(),
+                number: 123
+            })
+        );
+        assert_eq!(
+            ids.resolve("eng-42"),
+            Some(TodoReference::Tracked("eng-42".to_string()))
+        );
+        assert_eq!(ids.provider_for("eng-42"), Some("linear"));
+        // No Notion database takes the default pattern, so other keys aren't IDs
+        assert_eq!(ids.resolve("TASK-1"), None);
+    }
+
+    #[test]
+    fn databases_by_prefix() {
+        let ids = patterns(
+            r#"
+tasks: { type: notion, database_id: a, id_pattern: "TASK-\\d+" }
+bugs: { type: notion, database_id: b, id_pattern: "^BUG-\\d+$" }
+rest: { type: notion, database_id: c }
+"#,
+        );
+        assert_eq!(ids.provider_for("TASK-1"), Some("tasks"));
+        assert_eq!(ids.provider_for("BUG-1"), Some("bugs"));
+        assert_eq!(ids.provider_for("DOC-1"), Some("rest"));
+        // Patterns match whole IDs
+        assert_eq!(ids.provider_for("XTASK-1"), Some("rest"));
+        assert_eq!(ids.provider_for("task-1"), None);
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        let providers =
+            serde_yaml::from_str(r#"notion: { type: notion, database_id: a, id_pattern: "(" }"#)
+                .unwrap();
+        let err = IdPatterns::new(&providers).unwrap_err();
+        assert!(
+            err.starts_with("provider 'notion' has an invalid id_pattern"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,802 @@
+//! The table of languages we know how to scan, keyed by file name, extension and shebang.
+//! The built-in languages are defined here, and `.tudu.yaml` can add or override them
+//! under `languages:`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::LanguageConfig;
+use crate::lexer::{Quote, RawStrings, Syntax};
+
+#[derive(Debug, Clone)]
+pub struct Language {
+    pub name: String,
+    pub extensions: Vec<String>,
+    /// Exact file names, for files like `Dockerfile` that have no (useful) extension
+    pub filenames: Vec<String>,
+    /// Interpreters named in a `#!` line, like `bash` or `python`
+    pub interpreters: Vec<String>,
+    pub syntax: Syntax,
+}
+
+impl Language {
+    fn with_filenames(mut self, filenames: &[&str]) -> Self {
+        self.filenames = strings(filenames);
+        self
+    }
+
+    fn with_interpreters(mut self, interpreters: &[&str]) -> Self {
+        self.interpreters = strings(interpreters);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct LanguageTable {
+    languages: Vec<Language>,
+    by_extension: HashMap<String, usize>,
+    by_filename: HashMap<String, usize>,
+    by_interpreter: HashMap<String, usize>,
+    /// Used for files we don't know the language of: every common comment style, and no strings
+    generic: Syntax,
+}
+
+impl LanguageTable {
+    /// The built-in languages, with the ones from `.tudu.yaml` added on top. A configured language
+    /// with the same name as a built-in one replaces it, and configured extensions win over
+    /// built-in ones.
+    pub fn new(configured: &BTreeMap<String, LanguageConfig>) -> Result<Self, String> {
+        let mut languages = builtin_languages();
+
+        for (name, config) in configured {
+            let base = match &config.like {
+                Some(like) => match languages.iter().find(|language| &language.name == like) {
+                    Some(language) => language.syntax.clone(),
+                    None => {
+                        return Err(format!(
+                            "language '{}' is like '{}', which is not a built-in language",
+                            name, like
+                        ));
+                    }
+                },
+                None => match languages.iter().find(|language| &language.name == name) {
+                    Some(language) => language.syntax.clone(),
+                    None => Syntax::default(),
+                },
+            };
+
+            let language = Language {
+                name: name.clone(),
+                extensions: config.extensions.clone(),
+                filenames: config.filenames.clone(),
+                interpreters: config.interpreters.clone(),
+                syntax: config.apply_to(base),
+            };
+            match languages.iter_mut().find(|language| &language.name == name) {
+                Some(existing) => *existing = language,
+                None => languages.push(language),
+            }
+        }
+
+        Ok(Self::from_languages(languages))
+    }
+
+    fn from_languages(languages: Vec<Language>) -> Self {
+        let mut by_extension = HashMap::new();
+        let mut by_filename = HashMap::new();
+        let mut by_interpreter = HashMap::new();
+        // Later languages (the configured ones) take over anything claimed by an earlier one
+        for (i, language) in languages.iter().enumerate() {
+            for ext in &language.extensions {
+                by_extension.insert(ext.to_lowercase(), i);
+            }
+            for filename in &language.filenames {
+                by_filename.insert(filename.clone(), i);
+            }
+            for interpreter in &language.interpreters {
+                by_interpreter.insert(interpreter.clone(), i);
+            }
+        }
+
+        Self {
+            languages,
+            by_extension,
+            by_filename,
+            by_interpreter,
+            generic: Syntax {
+                line_comments: strings(&["//", "#"]),
+                block_comments: pairs(&[("/*", "*/"), ("<!--", "-->")]),
+                ..Syntax::default()
+            },
+        }
+    }
+
+    /// The language of a file from its name alone: an exact file name match, then its extension.
+    pub fn for_path(&self, path: &Path) -> Option<&Language> {
+        let filename = path.file_name()?.to_str()?;
+        if let Some(&i) = self.by_filename.get(filename) {
+            return Some(&self.languages[i]);
+        }
+
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        self.by_extension.get(&ext).map(|&i| &self.languages[i])
+    }
+
+    /// The language named by a `#!` line, e.g. `#!/bin/sh` or `#!/usr/bin/env -S python3 -u`.
+    pub fn for_shebang(&self, first_line: &str) -> Option<&Language> {
+        let command = first_line.strip_prefix("#!")?;
+        let mut words = command.split_whitespace();
+
+        let mut program = words.next()?.rsplit('/').next()?;
+        if program == "env" {
+            // Skip env's own flags and any VAR=value assignments
+            program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+        }
+
+        // `python3.12` is still `python`
+        let interpreter = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        self.by_interpreter
+            .get(interpreter)
+            .map(|&i| &self.languages[i])
+    }
+
+    /// The language of a file on disk. Files without an extension that we don't know by name
+    /// are checked for a shebang, which means reading the start of the file.
+    pub fn detect(&self, path: &Path) -> Option<&Language> {
+        if let Some(language) = self.for_path(path) {
+            return Some(language);
+        }
+        if path.extension().is_some() {
+            return None;
+        }
+
+        let mut start = [0; 256];
+        let len = File::open(path)
+            .and_then(|mut file| file.read(&mut start))
+            .ok()?;
+        let start = String::from_utf8_lossy(&start[..len]);
+        self.for_shebang(start.lines().next()?)
+    }
+
+    /// The syntax to scan a file with, falling back to a generic one for unknown files.
+    pub fn syntax_for(&self, path: &Path) -> &Syntax {
+        self.detect(path)
+            .map_or(&self.generic, |language| &language.syntax)
+    }
+
+    /// A built-in or configured language, by name.
+    pub fn get(&self, name: &str) -> Option<&Language> {
+        self.languages.iter().find(|language| language.name == name)
+    }
+}
+
+impl Default for LanguageTable {
+    fn default() -> Self {
+        Self::from_languages(builtin_languages())
+    }
+}
+
+impl LanguageConfig {
+    fn apply_to(&self, mut syntax: Syntax) -> Syntax {
+        if let Some(line_comments) = &self.line_comments {
+            syntax.line_comments = line_comments.clone();
+        }
+        if let Some(block_comments) = &self.block_comments {
+            syntax.block_comments = block_comments.clone();
+        }
+        if let Some(nested_comments) = self.nested_comments {
+            syntax.nested_comments = nested_comments;
+        }
+        if let Some(comment_needs_space) = self.comment_needs_space {
+            syntax.comment_needs_space = comment_needs_space;
+        }
+        if let Some(quotes) = &self.quotes {
+            syntax.quotes = quotes.clone();
+        }
+        syntax
+    }
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|s| s.to_string()).collect()
+}
+
+fn pairs(values: &[(&str, &str)]) -> Vec<(String, String)> {
+    values
+        .iter()
+        .map(|(open, close)| (open.to_string(), close.to_string()))
+        .collect()
+}
+
+fn double() -> Quote {
+    Quote::new("\"", "\"", true, false)
+}
+
+fn single() -> Quote {
+    Quote::new("'", "'", true, false)
+}
+
+fn triple_double() -> Quote {
+    Quote::new("\"\"\"", "\"\"\"", true, true)
+}
+
+fn triple_single() -> Quote {
+    Quote::new("'''", "'''", true, true)
+}
+
+fn language(name: &str, extensions: &[&str], syntax: Syntax) -> Language {
+    Language {
+        name: name.to_string(),
+        extensions: strings(extensions),
+        filenames: Vec::new(),
+        interpreters: Vec::new(),
+        syntax,
+    }
+}
+
+/// `//` and `/* */` comments, `"` strings and `'x'` char literals.
+fn c_like() -> Syntax {
+    Syntax {
+        line_comments: strings(&["//"]),
+        block_comments: pairs(&[("/*", "*/")]),
+        quotes: vec![double()],
+        char_literals: true,
+        ..Syntax::default()
+    }
+}
+
+/// `#` comments, like shells and most scripting languages.
+fn hash_like() -> Syntax {
+    Syntax {
+        line_comments: strings(&["#"]),
+        ..Syntax::default()
+    }
+}
+
+fn javascript() -> Syntax {
+    Syntax {
+        // JSX comments are `{/* ... */}`, which is just a block comment inside braces
+        quotes: vec![double(), single(), Quote::new("`", "`", true, true)],
+        char_literals: false,
+        ..c_like()
+    }
+}
+
+fn builtin_languages() -> Vec<Language> {
+    vec![
+        language("c", &["c", "h", "rc"], c_like()),
+        language(
+            "cpp",
+            &["cpp", "cc", "cxx", "hpp", "hh", "hxx"],
+            Syntax {
+                raw_strings: RawStrings::Cpp,
+                ..c_like()
+            },
+        ),
+        language(
+            "csharp",
+            &["cs"],
+            Syntax {
+                quotes: vec![Quote::new("@\"", "\"", false, true), double()],
+                ..c_like()
+            },
+        ),
+        // Java, Kotlin and Scala all have `"""` text blocks
+        language(
+            "java",
+            &["java", "kt", "kts", "scala"],
+            Syntax {
+                quotes: vec![triple_double(), double()],
+                ..c_like()
+            },
+        )
+        .with_filenames(&["Jenkinsfile"]),
+        language(
+            "swift",
+            &["swift"],
+            Syntax {
+                nested_comments: true,
+                quotes: vec![triple_double(), double()],
+                ..c_like()
+            },
+        ),
+        language(
+            "rust",
+            &["rs"],
+            Syntax {
+                nested_comments: true,
+                quotes: vec![Quote::new("\"", "\"", true, true)],
+                raw_strings: RawStrings::Rust,
+                ..c_like()
+            },
+        ),
+        language(
+            "go",
+            &["go"],
+            Syntax {
+                quotes: vec![double(), Quote::new("`", "`", false, true)],
+                ..c_like()
+            },
+        ),
+        language(
+            "javascript",
+            &["js", "mjs", "cjs", "jsx", "ts", "mts", "cts", "tsx"],
+            javascript(),
+        )
+        .with_interpreters(&["node", "nodejs", "deno", "bun"]),
+        language(
+            "php",
+            &["php"],
+            Syntax {
+                line_comments: strings(&["//", "#"]),
+                quotes: vec![
+                    Quote::new("\"", "\"", true, true),
+                    Quote::new("'", "'", true, true),
+                ],
+                char_literals: false,
+                ..c_like()
+            },
+        )
+        .with_interpreters(&["php"]),
+        language(
+            "fsharp",
+            &["fs", "fsi", "fsx"],
+            Syntax {
+                block_comments: pairs(&[("(*", "*)")]),
+                nested_comments: true,
+                quotes: vec![triple_double(), double()],
+                ..c_like()
+            },
+        ),
+        language(
+            "python",
+            &["py", "pyi", "pyw"],
+            Syntax {
+                docstrings: vec![triple_double(), triple_single()],
+                quotes: vec![triple_double(), triple_single(), double(), single()],
+                ..hash_like()
+            },
+        )
+        .with_filenames(&[
+            "SConstruct",
+            "SConscript",
+            "BUILD",
+            "BUILD.bazel",
+            "WORKSPACE",
+        ])
+        .with_interpreters(&["python", "pypy"]),
+        language(
+            "ruby",
+            &["rb"],
+            Syntax {
+                block_comments: pairs(&[("=begin", "=end")]),
+                quotes: vec![
+                    Quote::new("\"", "\"", true, true),
+                    Quote::new("'", "'", true, true),
+                ],
+                ..hash_like()
+            },
+        )
+        .with_filenames(&[
+            "Rakefile",
+            "Gemfile",
+            "Vagrantfile",
+            "Podfile",
+            "Fastfile",
+            "Brewfile",
+        ])
+        .with_interpreters(&["ruby"]),
+        language(
+            "shell",
+            &["sh", "bash", "zsh"],
+            Syntax {
+                comment_needs_space: true,
+                quotes: vec![
+                    Quote::new("\"", "\"", true, true),
+                    Quote::new("'", "'", false, true),
+                ],
+                ..hash_like()
+            },
+        )
+        .with_filenames(&[".bashrc", ".bash_profile", ".profile", ".zshrc", ".envrc"])
+        .with_interpreters(&["sh", "bash", "zsh", "dash", "ksh"]),
+        language(
+            "dockerfile",
+            &["dockerfile"],
+            Syntax {
+                comment_needs_space: true,
+                ..hash_like()
+            },
+        )
+        .with_filenames(&["Dockerfile", "Containerfile"]),
+        language(
+            "make",
+            &["mk", "mak"],
+            Syntax {
+                comment_needs_space: true,
+                ..hash_like()
+            },
+        )
+        .with_filenames(&["Makefile", "makefile", "GNUmakefile"]),
+        language(
+            "cmake",
+            &["cmake"],
+            Syntax {
+                block_comments: pairs(&[("#[[", "]]")]),
+                quotes: vec![Quote::new("\"", "\"", true, true)],
+                ..hash_like()
+            },
+        )
+        .with_filenames(&["CMakeLists.txt"]),
+        language(
+            "yaml",
+            &["yaml", "yml"],
+            Syntax {
+                comment_needs_space: true,
+                quotes: vec![double(), Quote::new("'", "'", false, false)],
+                ..hash_like()
+            },
+        ),
+        language(
+            "toml",
+            &["toml"],
+            Syntax {
+                quotes: vec![
+                    triple_double(),
+                    triple_single(),
+                    double(),
+                    Quote::new("'", "'", false, false),
+                ],
+                ..hash_like()
+            },
+        ),
+        language(
+            "css",
+            &["css"],
+            Syntax {
+                block_comments: pairs(&[("/*", "*/")]),
+                quotes: vec![double(), single()],
+                ..Syntax::default()
+            },
+        ),
+        language(
+            "scss",
+            &["scss", "less"],
+            Syntax {
+                line_comments: strings(&["//"]),
+                block_comments: pairs(&[("/*", "*/")]),
+                quotes: vec![double(), single()],
+                ..Syntax::default()
+            },
+        ),
+        // Text content is prose, so quotes are never strings
+        language(
+            "html",
+            &["html", "htm", "xml", "svg", "vue", "md", "markdown"],
+            Syntax {
+                block_comments: pairs(&[("<!--", "-->")]),
+                ..Syntax::default()
+            },
+        ),
+        // SQL escapes quotes by doubling them, which reads as two strings back to back
+        language(
+            "sql",
+            &["sql"],
+            Syntax {
+                line_comments: strings(&["--"]),
+                block_comments: pairs(&[("/*", "*/")]),
+                quotes: vec![
+                    Quote::new("'", "'", false, true),
+                    Quote::new("\"", "\"", false, true),
+                ],
+                ..Syntax::default()
+            },
+        ),
+        language(
+            "lua",
+            &["lua"],
+            Syntax {
+                line_comments: strings(&["--"]),
+                block_comments: pairs(&[("--[[", "]]")]),
+                quotes: vec![double(), single(), Quote::new("[[", "]]", false, true)],
+                ..Syntax::default()
+            },
+        )
+        .with_interpreters(&["lua", "luajit"]),
+        language(
+            "haskell",
+            &["hs", "lhs", "elm", "purs"],
+            Syntax {
+                line_comments: strings(&["--"]),
+                block_comments: pairs(&[("{-", "-}")]),
+                nested_comments: true,
+                quotes: vec![double()],
+                // Also handles primes in names, like `x'`
+                char_literals: true,
+                ..Syntax::default()
+            },
+        ),
+        language(
+            "lisp",
+            &[
+                "lisp", "lsp", "cl", "el", "scm", "ss", "rkt", "clj", "cljs", "cljc", "edn",
+            ],
+            Syntax {
+                line_comments: strings(&[";"]),
+                block_comments: pairs(&[("#|", "|#")]),
+                nested_comments: true,
+                quotes: vec![Quote::new("\"", "\"", true, true)],
+                ..Syntax::default()
+            },
+        )
+        .with_interpreters(&["sbcl", "guile", "racket", "bb"]),
+        language(
+            "asm",
+            &["asm", "s", "nasm"],
+            Syntax {
+                line_comments: strings(&[";"]),
+                quotes: vec![double(), single()],
+                ..Syntax::default()
+            },
+        ),
+        language(
+            "erlang",
+            &["erl", "hrl"],
+            Syntax {
+                line_comments: strings(&["%"]),
+                quotes: vec![double()],
+                ..Syntax::default()
+            },
+        )
+        .with_interpreters(&["escript"]),
+        // `\%` is a literal percent sign, so it's skipped like a tiny string
+        language(
+            "latex",
+            &["tex", "sty", "cls", "bib"],
+            Syntax {
+                line_comments: strings(&["%"]),
+                quotes: vec![Quote::new("\\%", "", false, false)],
+                ..Syntax::default()
+            },
+        ),
+        // `'` is also the transpose operator, but unclosed quotes are ignored anyway
+        language(
+            "matlab",
+            &["m"],
+            Syntax {
+                line_comments: strings(&["%"]),
+                block_comments: pairs(&[("%{", "%}")]),
+                quotes: vec![double(), single()],
+                ..Syntax::default()
+            },
+        ),
+        language(
+            "visualbasic",
+            &["vb", "vbs", "bas"],
+            Syntax {
+                line_comments: strings(&["'"]),
+                quotes: vec![double()],
+                ..Syntax::default()
+            },
+        ),
+        language(
+            "ocaml",
+            &["ml", "mli"],
+            Syntax {
+                block_comments: pairs(&[("(*", "*)")]),
+                nested_comments: true,
+                quotes: vec![Quote::new("\"", "\"", true, true)],
+                // Also handles type variables, like `'a`
+                char_literals: true,
+                ..Syntax::default()
+            },
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::comments;
+
+    fn bodies<'a>(table: &LanguageTable, file_name: &str, source: &'a str) -> Vec<&'a str> {
+        comments(source, table.syntax_for(Path::new(file_name)))
+            .into_iter()
+            .map(|c| &source[c.body])
+            .collect()
+    }
+
+    #[test]
+    fn lookup_by_extension() {
+        let table = LanguageTable::default();
+        assert_eq!(
+            table.for_path(Path::new("src/main.rs")).unwrap().name,
+            "rust"
+        );
+        assert_eq!(
+            table.for_path(Path::new("App.TSX")).unwrap().name,
+            "javascript"
+        );
+        assert!(table.for_path(Path::new("image.png")).is_none());
+        assert!(table.for_path(Path::new("LICENSE")).is_none());
+    }
+
+    #[test]
+    fn lookup_by_filename() {
+        let table = LanguageTable::default();
+        assert_eq!(
+            table.for_path(Path::new("app/Dockerfile")).unwrap().name,
+            "dockerfile"
+        );
+        assert_eq!(table.for_path(Path::new("Makefile")).unwrap().name, "make");
+        assert_eq!(
+            table.for_path(Path::new("Jenkinsfile")).unwrap().name,
+            "java"
+        );
+        // The file name wins over the `.txt` extension
+        assert_eq!(
+            table.for_path(Path::new("CMakeLists.txt")).unwrap().name,
+            "cmake"
+        );
+        assert!(table.for_path(Path::new("notes.txt")).is_none());
+    }
+
+    #[test]
+    fn lookup_by_shebang() {
+        let table = LanguageTable::default();
+        let name = |line| {
+            table
+                .for_shebang(line)
+                .map(|language| language.name.as_str())
+        };
+
+        assert_eq!(name("#!/bin/sh"), Some("shell"));
+        assert_eq!(name("#!/usr/bin/env bash"), Some("shell"));
+        assert_eq!(name("#!/usr/bin/env -S python3 -u"), Some("python"));
+        assert_eq!(
+            name("#!/usr/bin/env NODE_ENV=production node"),
+            Some("javascript")
+        );
+        assert_eq!(name("#!/usr/local/bin/python3.12"), Some("python"));
+        assert_eq!(name("#!/usr/bin/env cobol"), None);
+        assert_eq!(name("# just a comment"), None);
+    }
+
+    #[test]
+    fn unknown_files_get_the_generic_syntax() {
+        let table = LanguageTable::default();
+        assert_eq!(
+            bodies(&table, "notes.txt", "# one\n// two"),
+            vec![" one", " two"]
+        );
+    }
+
+    #[test]
+    fn dash_dash_comments() {
+        let table = LanguageTable::default();
+        assert_eq!(
+            bodies(&table, "q.sql", "SELECT '--x' FROM t; -- real"),
+            vec![" real"]
+        );
+        assert_eq!(
+            bodies(&table, "m.hs", "x' = 1 {- a {- b -} c -}"),
+            vec![" a {- b -} c "]
+        );
+        assert_eq!(
+            bodies(&table, "a.lua", "--[[ block ]] -- line"),
+            vec![" block ", " line"]
+        );
+    }
+
+    #[test]
+    fn semicolon_and_percent_comments() {
+        let table = LanguageTable::default();
+        assert_eq!(
+            bodies(&table, "core.clj", "(str \";\") ; real"),
+            vec![" real"]
+        );
+        assert_eq!(
+            bodies(&table, "boot.asm", "mov ax, 1 ; real"),
+            vec![" real"]
+        );
+        assert_eq!(bodies(&table, "a.erl", "X = \"%\". % real"), vec![" real"]);
+        assert_eq!(
+            bodies(&table, "paper.tex", "50\\% done % real"),
+            vec![" real"]
+        );
+        assert_eq!(
+            bodies(&table, "f.m", "%{\nblock\n%}\nx = a'; % real"),
+            vec!["\nblock\n", " real"]
+        );
+    }
+
+    #[test]
+    fn other_comment_styles() {
+        let table = LanguageTable::default();
+        assert_eq!(bodies(&table, "mod.vb", "x = \"'\" ' real"), vec![" real"]);
+        assert_eq!(
+            bodies(&table, "a.ml", "let c = '\"' (* a (* b *) *)"),
+            vec![" a (* b *) "]
+        );
+        assert_eq!(
+            bodies(&table, "App.jsx", "<div>{/* real */}</div>"),
+            vec![" real "]
+        );
+    }
+
+    #[test]
+    fn configured_language() {
+        let configured = BTreeMap::from([(
+            "terraform".to_string(),
+            LanguageConfig {
+                extensions: vec!["tf".to_string()],
+                line_comments: Some(vec!["#".to_string(), "//".to_string()]),
+                block_comments: Some(vec![("/*".to_string(), "*/".to_string())]),
+                ..LanguageConfig::default()
+            },
+        )]);
+        let table = LanguageTable::new(&configured).unwrap();
+
+        assert_eq!(
+            table.for_path(Path::new("main.tf")).unwrap().name,
+            "terraform"
+        );
+        assert_eq!(
+            bodies(&table, "main.tf", "# a\n// b\n/* c */"),
+            vec![" a", " b", " c "]
+        );
+    }
+
+    #[test]
+    fn configured_language_like_a_builtin() {
+        let configured = BTreeMap::from([(
+            "groovy".to_string(),
+            LanguageConfig {
+                extensions: vec!["groovy".to_string(), "gradle".to_string()],
+                like: Some("java".to_string()),
+                ..LanguageConfig::default()
+            },
+        )]);
+        let table = LanguageTable::new(&configured).unwrap();
+
+        assert_eq!(
+            bodies(&table, "build.gradle", "s = \"// no\" // yes"),
+            vec![" yes"]
+        );
+    }
+
+    #[test]
+    fn configured_language_replaces_builtin() {
+        let configured = BTreeMap::from([(
+            "javascript".to_string(),
+            LanguageConfig {
+                extensions: vec!["js".to_string(), "es6".to_string()],
+                ..LanguageConfig::default()
+            },
+        )]);
+        let table = LanguageTable::new(&configured).unwrap();
+
+        assert_eq!(
+            table.for_path(Path::new("a.es6")).unwrap().name,
+            "javascript"
+        );
+        // The old extensions go with the old definition
+        assert!(table.for_path(Path::new("a.tsx")).is_none());
+        // But the syntax is kept, since none was configured
+        assert_eq!(bodies(&table, "a.es6", "'//' // real"), vec![" real"]);
+    }
+
+    #[test]
+    fn unknown_like_is_an_error() {
+        let configured = BTreeMap::from([(
+            "mystery".to_string(),
+            LanguageConfig {
+                like: Some("cobol".to_string()),
+                ..LanguageConfig::default()
+            },
+        )]);
+        assert!(LanguageTable::new(&configured).is_err());
+    }
+}
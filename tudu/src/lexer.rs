@@ -0,0 +1,565 @@
+//! A small, language-aware lexer that finds the comments in a source file.
+//! It only tracks enough state (strings, char literals, raw strings) to tell a real comment
+//! apart from something that just looks like one, e.g. `"#TODO"` inside a string.
+
+use std::ops::Range;
+
+/// How a string literal is delimited.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Quote {
+    pub open: String,
+    pub close: String,
+    /// Whether a backslash escapes the next character
+    #[serde(default = "default_true")]
+    pub escapes: bool,
+    /// Whether the string may span lines. Single-line strings that aren't closed by the end
+    /// of the line are treated as stray quotes (e.g. an apostrophe in YAML prose).
+    #[serde(default)]
+    pub multiline: bool,
+}
+
+impl Quote {
+    pub fn new(open: &str, close: &str, escapes: bool, multiline: bool) -> Self {
+        Self {
+            open: open.to_string(),
+            close: close.to_string(),
+            escapes,
+            multiline,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Language-specific raw string syntax, which needs more than a fixed open/close pair.
+#[derive(serde::Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RawStrings {
+    #[default]
+    None,
+    /// `r"..."`, `r#"..."#`, `br##"..."##`
+    Rust,
+    /// `R"(...)"`, `R"delim(...)delim"`
+    Cpp,
+}
+
+/// The comment and string syntax of a language. The built-in ones live in `languages`.
+#[derive(Debug, Clone, Default)]
+pub struct Syntax {
+    pub line_comments: Vec<String>,
+    /// Checked before line comments, so `--[[` (Lua) wins over `--`
+    pub block_comments: Vec<(String, String)>,
+    /// Whether block comments nest, as in Rust and Swift
+    pub nested_comments: bool,
+    /// Whether a line comment must start a line or follow whitespace, as in shell (`$#`) and YAML
+    pub comment_needs_space: bool,
+    /// Strings that are read as block comments when they open a line, like Python docstrings
+    pub docstrings: Vec<Quote>,
+    /// Checked in order, so longer delimiters (`"""`) must come before shorter ones (`"`)
+    pub quotes: Vec<Quote>,
+    /// Whether `'x'` is a char literal rather than a string (and `'a` may be a lifetime)
+    pub char_literals: bool,
+    pub raw_strings: RawStrings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommentKind {
+    Line,
+    Block,
+}
+
+/// A comment found in a source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub kind: CommentKind,
+    /// Byte range of the whole comment, including its delimiters
+    pub span: Range<usize>,
+    /// Byte range of the text between the delimiters
+    pub body: Range<usize>,
+    /// 1-based line the comment starts on
+    pub start_line: usize,
+}
+
+impl Comment {
+    /// The lines of the comment body, each with its 1-based line number.
+    pub fn lines<'a>(&self, source: &'a str) -> impl Iterator<Item = (usize, &'a str)> {
+        let start_line = self.start_line;
+        source[self.body.clone()]
+            .split('\n')
+            .enumerate()
+            .map(move |(i, line)| (start_line + i, line))
+    }
+}
+
+/// Finds every comment in `source`, skipping over anything inside string and char literals.
+pub fn comments(source: &str, syntax: &Syntax) -> Vec<Comment> {
+    let mut lexer = Lexer {
+        bytes: source.as_bytes(),
+        pos: 0,
+        line: 1,
+    };
+    let mut comments = Vec::new();
+
+    while lexer.pos < lexer.bytes.len() {
+        if let Some(comment) = lexer.comment(syntax) {
+            comments.push(comment);
+        } else if !lexer.string(syntax) {
+            lexer.advance(1);
+        }
+    }
+
+    comments
+}
+
+/// A region of a source file. The byte offsets index into the file's decoded text; lines and
+/// columns are 1-based, columns count characters, and the end is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+/// Where each line of a source file starts, to turn byte offsets into lines and columns.
+pub struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    /// The 1-based line and column of a byte offset.
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line_start = self.line_starts[line - 1];
+        let column = self.source[line_start..offset].chars().count() + 1;
+        (line, column)
+    }
+
+    pub fn span(&self, range: Range<usize>) -> Span {
+        let (start_line, start_column) = self.position(range.start);
+        let (end_line, end_column) = self.position(range.end);
+        Span {
+            start: range.start,
+            end: range.end,
+            start_line,
+            start_column,
+            end_line,
+            end_column,
+        }
+    }
+}
+
+struct Lexer<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl Lexer<'_> {
+    fn at(&self, pos: usize, token: &str) -> bool {
+        self.bytes[pos.min(self.bytes.len())..].starts_with(token.as_bytes())
+    }
+
+    /// Moves forward `n` bytes, keeping track of line numbers.
+    fn advance(&mut self, n: usize) {
+        let end = (self.pos + n).min(self.bytes.len());
+        self.line += self.bytes[self.pos..end]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count();
+        self.pos = end;
+    }
+
+    /// Position of the next `token` at or after `from`, or the end of input.
+    fn find(&self, from: usize, token: &str) -> usize {
+        let token = token.as_bytes();
+        (from..self.bytes.len())
+            .find(|&i| self.bytes[i..].starts_with(token))
+            .unwrap_or(self.bytes.len())
+    }
+
+    /// Whether only whitespace comes before the current position on its line.
+    fn at_line_start(&self) -> bool {
+        self.bytes[..self.pos]
+            .iter()
+            .rev()
+            .take_while(|&&b| b != b'\n')
+            .all(|b| b.is_ascii_whitespace())
+    }
+
+    fn prev_is(&self, pred: impl Fn(u8) -> bool) -> bool {
+        self.pos > 0 && pred(self.bytes[self.pos - 1])
+    }
+
+    /// Consumes a comment starting at the current position, if there is one.
+    fn comment(&mut self, syntax: &Syntax) -> Option<Comment> {
+        let start = self.pos;
+        let start_line = self.line;
+
+        for (open, close) in &syntax.block_comments {
+            if self.at(start, open) {
+                let body_start = start + open.len();
+                let (body_end, end) = self.block_comment_end(body_start, open, close, syntax);
+                self.advance(end - start);
+                return Some(Comment {
+                    kind: CommentKind::Block,
+                    span: start..end,
+                    body: body_start..body_end,
+                    start_line,
+                });
+            }
+        }
+
+        if !syntax.docstrings.is_empty() && self.at_line_start() {
+            for quote in &syntax.docstrings {
+                if self.at(start, &quote.open)
+                    && let Some(end) = self.quoted_end(quote)
+                {
+                    let body_start = start + quote.open.len();
+                    let body_end = if self.at(end - quote.close.len(), &quote.close) {
+                        end - quote.close.len()
+                    } else {
+                        // Unterminated, so it runs to the end of the input
+                        end
+                    };
+                    self.advance(end - start);
+                    return Some(Comment {
+                        kind: CommentKind::Block,
+                        span: start..end,
+                        body: body_start.min(body_end)..body_end,
+                        start_line,
+                    });
+                }
+            }
+        }
+
+        for token in &syntax.line_comments {
+            if self.at(start, token)
+                && (!syntax.comment_needs_space
+                    || self.prev_is(|b| b.is_ascii_whitespace())
+                    || start == 0)
+            {
+                let end = self.find(start, "\n");
+                // Leave a trailing `\r` out of the comment
+                let end = if end > start && self.bytes[end - 1] == b'\r' {
+                    end - 1
+                } else {
+                    end
+                };
+                self.advance(end - start);
+                return Some(Comment {
+                    kind: CommentKind::Line,
+                    span: start..end,
+                    body: start + token.len()..end,
+                    start_line,
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Returns the end of the body and the end of the comment. Unterminated comments run to
+    /// the end of the input.
+    fn block_comment_end(
+        &self,
+        mut pos: usize,
+        open: &str,
+        close: &str,
+        syntax: &Syntax,
+    ) -> (usize, usize) {
+        let mut depth = 1;
+
+        while pos < self.bytes.len() {
+            if self.at(pos, close) {
+                depth -= 1;
+                if depth == 0 {
+                    return (pos, pos + close.len());
+                }
+                pos += close.len();
+            } else if syntax.nested_comments && self.at(pos, open) {
+                depth += 1;
+                pos += open.len();
+            } else {
+                pos += 1;
+            }
+        }
+
+        (self.bytes.len(), self.bytes.len())
+    }
+
+    /// Consumes a string or char literal starting at the current position, if there is one.
+    fn string(&mut self, syntax: &Syntax) -> bool {
+        if let Some(end) = self.raw_string_end(syntax) {
+            self.advance(end - self.pos);
+            return true;
+        }
+
+        if syntax.char_literals && self.at(self.pos, "'") {
+            // A lone quote is a lifetime or label (`'a`), not a literal, so just step over it
+            let end = self.char_literal_end().unwrap_or(self.pos + 1);
+            self.advance(end - self.pos);
+            return true;
+        }
+
+        for quote in &syntax.quotes {
+            if self.at(self.pos, &quote.open)
+                && let Some(end) = self.quoted_end(quote)
+            {
+                self.advance(end - self.pos);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// End of the quoted string at the current position, or `None` if it's a stray quote.
+    fn quoted_end(&self, quote: &Quote) -> Option<usize> {
+        let mut pos = self.pos + quote.open.len();
+
+        while pos < self.bytes.len() {
+            if quote.escapes && self.bytes[pos] == b'\\' {
+                pos += 2;
+            } else if self.at(pos, &quote.close) {
+                return Some(pos + quote.close.len());
+            } else if self.bytes[pos] == b'\n' && !quote.multiline {
+                return None;
+            } else {
+                pos += 1;
+            }
+        }
+
+        // Unterminated multi-line strings run to the end of the input
+        quote.multiline.then_some(self.bytes.len())
+    }
+
+    /// End of the char literal at the current position, e.g. `'a'`, `'\n'` or `'é'`.
+    fn char_literal_end(&self) -> Option<usize> {
+        let start = self.pos + 1;
+        let first = *self.bytes.get(start)?;
+
+        if first == b'\\' {
+            // Escapes can be long, like '\u{1F600}', but never cross a line
+            let end = (start + 1..self.bytes.len())
+                .take_while(|&i| self.bytes[i] != b'\n')
+                .find(|&i| self.bytes[i] == b'\'' && i > start + 1)?;
+            return Some(end + 1);
+        }
+
+        let char_len = match first {
+            b if b < 0x80 => 1,
+            b if b >= 0xF0 => 4,
+            b if b >= 0xE0 => 3,
+            _ => 2,
+        };
+        (self.bytes.get(start + char_len) == Some(&b'\'')).then_some(start + char_len + 1)
+    }
+
+    fn raw_string_end(&self, syntax: &Syntax) -> Option<usize> {
+        // Raw string prefixes can't continue an identifier, e.g. `bar"` isn't a raw string
+        let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+
+        match syntax.raw_strings {
+            RawStrings::None => None,
+            RawStrings::Rust => {
+                let mut pos = self.pos;
+                if self.prev_is(is_ident) {
+                    return None;
+                }
+                if self.at(pos, "b") || self.at(pos, "c") {
+                    pos += 1;
+                }
+                if !self.at(pos, "r") {
+                    return None;
+                }
+                pos += 1;
+
+                let hashes = self.bytes[pos..].iter().take_while(|&&b| b == b'#').count();
+                pos += hashes;
+                if !self.at(pos, "\"") {
+                    return None;
+                }
+
+                let close = format!("\"{}", "#".repeat(hashes));
+                let end = self.find(pos + 1, &close);
+                Some((end + close.len()).min(self.bytes.len()))
+            }
+            RawStrings::Cpp => {
+                let mut pos = self.pos;
+                if self.prev_is(is_ident) {
+                    return None;
+                }
+                // Encoding prefixes: u8R"(...)", LR"(...)", etc.
+                for prefix in ["u8", "u", "U", "L"] {
+                    if self.at(pos, prefix) && self.at(pos + prefix.len(), "R\"") {
+                        pos += prefix.len();
+                        break;
+                    }
+                }
+                if !self.at(pos, "R\"") {
+                    return None;
+                }
+                pos += 2;
+
+                let delim_len = self.bytes[pos..].iter().position(|&b| b == b'(')?;
+                let delim = std::str::from_utf8(&self.bytes[pos..pos + delim_len]).ok()?;
+                let close = format!("){}\"", delim);
+                let end = self.find(pos + delim_len + 1, &close);
+                Some((end + close.len()).min(self.bytes.len()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::LanguageTable;
+
+    fn syntax(name: &str) -> Syntax {
+        LanguageTable::default().get(name).unwrap().syntax.clone()
+    }
+
+    fn bodies<'a>(source: &'a str, syntax: &Syntax) -> Vec<&'a str> {
+        comments(source, syntax)
+            .into_iter()
+            .map(|c| &source[c.body])
+            .collect()
+    }
+
+    #[test]
+    fn line_and_block_comments() {
+        let source = "let x = 1; // one\n/* two\n three */ let y = 2;\n";
+        let found = comments(source, &syntax("rust"));
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].kind, CommentKind::Line);
+        assert_eq!(&source[found[0].body.clone()], " one");
+        assert_eq!(found[0].start_line, 1);
+        assert_eq!(found[1].kind, CommentKind::Block);
+        assert_eq!(&source[found[1].span.clone()], "/* two\n three */");
+        assert_eq!(found[1].start_line, 2);
+    }
+
+    #[test]
+    fn comment_markers_inside_strings_are_ignored() {
+        let source = r#"let a = "// TODO: not a comment"; let b = "/* nor this */"; // real"#;
+        assert_eq!(bodies(source, &syntax("rust")), vec![" real"]);
+
+        let source = "x = '# TODO' + \"#TODO\"  # real\n";
+        assert_eq!(bodies(source, &syntax("python")), vec![" real"]);
+
+        let source = "const html = '<!-- TODO -->'; const url = `http://x/#TODO`; // real";
+        assert_eq!(bodies(source, &syntax("javascript")), vec![" real"]);
+    }
+
+    #[test]
+    fn escaped_quotes() {
+        let source = r#"s = "a \" // not a comment"; // real"#;
+        assert_eq!(bodies(source, &syntax("c")), vec![" real"]);
+    }
+
+    #[test]
+    fn rust_raw_strings_and_lifetimes() {
+        let source = r###"let s = r#"a " // still a string"#; fn f<'a>(x: &'a str) -> char { '"' } // real"###;
+        assert_eq!(bodies(source, &syntax("rust")), vec![" real"]);
+    }
+
+    #[test]
+    fn cpp_raw_strings() {
+        let source = r#"auto s = R"x(a )" // string )x"; // real"#;
+        assert_eq!(bodies(source, &syntax("cpp")), vec![" real"]);
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        let source = "/* outer /* inner */ still outer */ // after";
+        assert_eq!(
+            bodies(source, &syntax("rust")),
+            vec![" outer /* inner */ still outer ", " after"]
+        );
+        // C comments don't nest, so the first `*/` closes it
+        assert_eq!(bodies(source, &syntax("c"))[0], " outer /* inner ");
+    }
+
+    #[test]
+    fn shell_hash_needs_space() {
+        let source = "echo ${#arr[@]} $# # real\n";
+        assert_eq!(bodies(source, &syntax("shell")), vec![" real"]);
+    }
+
+    #[test]
+    fn stray_apostrophes_in_yaml() {
+        let source = "title: don't panic # real\n";
+        assert_eq!(bodies(source, &syntax("yaml")), vec![" real"]);
+    }
+
+    #[test]
+    fn multiline_strings_track_lines() {
+        let source = "s = \"\"\"\n# not a comment\n\"\"\"\n# real\n";
+        let found = comments(source, &syntax("python"));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].start_line, 4);
+    }
+
+    #[test]
+    fn python_docstrings() {
+        let source = "def f():\n    \"\"\"Docs.\n\n    TODO: more\n    \"\"\"\n    x = \"\"\"not a docstring\"\"\"\n";
+        let found = comments(source, &syntax("python"));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, CommentKind::Block);
+        assert_eq!(found[0].start_line, 2);
+        assert_eq!(
+            &source[found[0].body.clone()],
+            "Docs.\n\n    TODO: more\n    "
+        );
+    }
+
+    #[test]
+    fn line_index_spans() {
+        let source = "ab\n// caf\u{e9} TODO\r\nend";
+        let index = LineIndex::new(source);
+
+        assert_eq!(index.position(0), (1, 1));
+        assert_eq!(index.position(3), (2, 1));
+        let todo = source.find("TODO").unwrap();
+        // Columns count characters, not bytes
+        assert_eq!(index.position(todo), (2, 9));
+        assert_eq!(index.position(source.len()), (3, 4));
+
+        let span = index.span(todo..source.find('\r').unwrap());
+        assert_eq!(
+            (
+                span.start_line,
+                span.start_column,
+                span.end_line,
+                span.end_column
+            ),
+            (2, 9, 2, 13)
+        );
+    }
+
+    #[test]
+    fn comment_lines() {
+        let source = "/*\n * TODO: a\n */";
+        let found = comments(source, &syntax("c"));
+        let lines: Vec<_> = found[0].lines(source).collect();
+        assert_eq!(lines, vec![(1, ""), (2, " * TODO: a"), (3, " ")]);
+    }
+}
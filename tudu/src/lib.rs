@@ -1,64 +1,352 @@
 use clap::Parser;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
+pub mod attributes;
+pub mod canonical;
+pub mod encoding;
+pub mod error;
+pub mod ids;
+pub mod languages;
+pub mod lexer;
+pub mod output;
+pub mod parser;
 pub mod providers;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TodoReference {
-    Untracked,                     // Plain TODO: without ID
-    Tracked(String),               // TODO(TASK-123):
-    New { title: Option<String> }, // TODO(new="Create user service"):
+    Untracked,                               // Plain TODO: without ID
+    Tracked(String),                         // TODO(TASK-123):
+    GitHub { repo: String, number: u64 },    // TODO(gh:org/repo#1234):
+    GitLab { project: String, number: u64 }, // TODO(gl:group/proj#77):
+    Jira { key: String },                    // TODO(jira:PROJ-456):
+    Notion { page_id: String },              // TODO(nt:PAGE_ID):
+    New { title: Option<String> },           // TODO(new="Create user service"):
+}
+
+impl TodoReference {
+    /// The kind of provider that tracks this reference. Shorthand IDs like `TASK-123` are the
+    /// ID property of the configured Notion databases.
+    pub fn provider(&self) -> Option<ProviderKind> {
+        match self {
+            TodoReference::Tracked(_) | TodoReference::Notion { .. } => Some(ProviderKind::Notion),
+            TodoReference::GitHub { .. } => Some(ProviderKind::GitHub),
+            TodoReference::GitLab { .. } => Some(ProviderKind::GitLab),
+            TodoReference::Jira { .. } => Some(ProviderKind::Jira),
+            TodoReference::Untracked | TodoReference::New { .. } => None,
+        }
+    }
+
+    /// The issue this references, as written in a TODO, e.g. `gh:org/repo#1234` or `TASK-123`.
+    pub fn issue(&self) -> Option<String> {
+        match self {
+            TodoReference::Tracked(id) => Some(id.clone()),
+            TodoReference::GitHub { repo, number } => Some(format!("gh:{}#{}", repo, number)),
+            TodoReference::GitLab { project, number } => Some(format!("gl:{}#{}", project, number)),
+            TodoReference::Jira { key } => Some(format!("jira:{}", key)),
+            TodoReference::Notion { page_id } => Some(format!("nt:{}", page_id)),
+            TodoReference::Untracked | TodoReference::New { .. } => None,
+        }
+    }
+}
+
+/// Writes a reference the way it goes between a TODO's parentheses, e.g. `TASK-123`,
+/// `gh:org/repo#1234` or `new="Add retries"`. Untracked references write nothing.
+impl std::fmt::Display for TodoReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TodoReference::Untracked => Ok(()),
+            TodoReference::New { title: None } => write!(f, "new"),
+            TodoReference::New { title: Some(title) } => {
+                write!(f, "new={}", parser::quote(title))
+            }
+            reference => write!(f, "{}", reference.issue().unwrap_or_default()),
+        }
+    }
+}
+
+/// The issue trackers `tudu` knows about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderKind {
+    Notion,
+    Jira,
+    GitHub,
+    GitLab,
+}
+
+impl std::fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderKind::Notion => write!(f, "notion"),
+            ProviderKind::Jira => write!(f, "jira"),
+            ProviderKind::GitHub => write!(f, "github"),
+            ProviderKind::GitLab => write!(f, "gitlab"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TodoAttributeValue {
-    Flag(bool),        // bidir
-    Text(String),      // assignee=alice
-    List(Vec<String>), // labels=urgent,backend
+    Flag(bool),             // bidir
+    Text(String),           // assignee=alice
+    List(Vec<String>),      // labels=urgent,backend
+    Date(attributes::Date), // due=2025-10-01
+    Number(i64),            // estimate=3
+}
+
+/// Writes a value the way it goes after `key=`, quoted if it has to be.
+impl std::fmt::Display for TodoAttributeValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TodoAttributeValue::Flag(flag) => write!(f, "{}", flag),
+            TodoAttributeValue::Text(text) => write!(f, "{}", parser::quote_if_needed(text)),
+            TodoAttributeValue::List(values) => {
+                let values: Vec<_> = values
+                    .iter()
+                    .map(|value| parser::quote_if_needed(value))
+                    .collect();
+                write!(f, "{}", values.join(","))
+            }
+            TodoAttributeValue::Date(date) => write!(f, "{}", date),
+            TodoAttributeValue::Number(number) => write!(f, "{}", number),
+        }
+    }
+}
+
+/// How serious a marker is. Ordered, so `Severity::Error > Severity::Info`.
+#[derive(
+    serde::Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct TodoItem {
     pub file_path: PathBuf,
+    /// The line the TODO starts on
     pub line_number: usize,
+    /// The last line of the TODO, which differs from `line_number` for multi-line TODOs
+    pub end_line_number: usize,
     pub line_content: String,
-    pub reference: Option<TodoReference>,
+    /// The full TODO text, from the marker on, with comment delimiters and decoration removed.
+    /// Multi-line TODOs keep one line of text per source line.
+    pub body: String,
+    /// The marker keyword as written in the config, e.g. `FIXME` or `@todo`
+    pub marker: String,
+    pub severity: Severity,
+    /// The text after the marker and its `(...)`, e.g. `fix this` in `TODO(TASK-1): fix this`.
+    /// Multi-line TODOs keep one line of text per source line.
+    pub message: String,
+    /// The TODO's first line of text, from the marker to the end of the line, without comment
+    /// delimiters
+    pub text_span: lexer::Span,
+    /// The comment the TODO is in, delimiters included. For line comments, this covers any
+    /// continuation lines too.
+    pub comment_span: lexer::Span,
+    /// The `(...)` right after the marker, parentheses included
+    pub reference_span: Option<lexer::Span>,
+    /// The issues the TODO references, in order. Empty for untracked TODOs.
+    pub references: Vec<TodoReference>,
+    /// The person or team in `TODO(alice)` or `TODO(@team-infra)`, without the `@`
+    pub owner: Option<String>,
     pub attributes: Option<HashMap<String, TodoAttributeValue>>,
+    /// What's malformed about the TODO, if anything
+    pub diagnostics: Vec<parser::Diagnostic>,
 }
 
 #[derive(Parser)]
+#[command(after_help = "Exit codes:
+  0  Scan finished, nothing met the --fail-on threshold
+  1  A TODO met the --fail-on threshold, or (in validate mode) a TODO is malformed,
+     or `fmt --check` found a TODO to rewrite
+  2  Invalid configuration
+  3  An issue provider failed while filing issues in sync mode
+  4  The path to scan or the config file couldn't be read
+
+Files that can't be read are reported as warnings and skipped; they don't change the exit code.")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// File or directory to scan
-    #[arg(value_name = "PATH")]
-    pub path: PathBuf,
+    #[arg(value_name = "PATH", required = true)]
+    pub path: Option<PathBuf>,
 
     /// Show verbose output
     #[arg(short, long)]
     pub verbose: bool,
 
-    /// Output format: standard or json (overrides config)  
-    #[arg(long)]
-    pub format: Option<String>,
+    /// Output format (overrides config)
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub format: Option<Format>,
+
+    /// Only report TODOs at or above this severity (overrides config)
+    #[arg(long, value_enum, value_name = "SEVERITY")]
+    pub min_severity: Option<Severity>,
+
+    /// Exit with a non-zero code if any reported TODO is at or above this severity
+    #[arg(long, value_enum, value_name = "SEVERITY")]
+    pub fail_on: Option<Severity>,
+
+    /// Only report TODOs owned by this person or team, as in TODO(alice)
+    #[arg(long, value_name = "OWNER")]
+    pub owner: Option<String>,
+
+    /// Group TODOs in the output by file or by owner
+    #[arg(long, value_enum, value_name = "GROUP", default_value_t = GroupBy::File)]
+    pub group_by: GroupBy,
+
+    /// Number of threads to scan with (defaults to the number of CPUs)
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<usize>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Rewrite every TODO into the canonical `TODO(<ref>[, attrs]): text` form
+    Fmt {
+        /// File or directory to format
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+
+        /// Don't rewrite anything; list the TODOs that would be, and exit with 1 if there are any
+        #[arg(long)]
+        check: bool,
+
+        /// Number of threads to scan with (defaults to the number of CPUs)
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+    },
+}
+
+/// How the results are printed.
+#[derive(serde::Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// For people to read
+    #[default]
+    Standard,
+    /// One versioned JSON document; see `schema/output.v1.schema.json`
+    Json,
+    /// One JSON record per line, written as files are scanned, then a summary record
+    Ndjson,
+    /// A SARIF 2.1.0 log, for code scanning tools
+    Sarif,
+    /// GitHub Actions annotations, and a job summary in `$GITHUB_STEP_SUMMARY`
+    Github,
+}
+
+/// How TODOs are grouped in the output.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    File,
+    Owner,
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct Config {
     #[serde(default)]
     pub scan: ScanConfig,
+    /// Marker keywords to look for, and how serious each one is
+    #[serde(default = "default_markers")]
+    pub markers: BTreeMap<String, Severity>,
+    /// Languages to add to (or override in) the built-in table
+    #[serde(default)]
+    pub languages: BTreeMap<String, LanguageConfig>,
+    /// Types of attribute keys, on top of the built-in ones
+    #[serde(default)]
+    pub attributes: AttributesConfig,
     #[serde(default = "default_mode")]
     pub mode: String,
-    pub providers: HashMap<String, ProviderConfig>,
+    pub providers: BTreeMap<String, ProviderConfig>,
+    /// Owner handles, as in `TODO(alice)`, and who each one is in each provider, by provider name
+    #[serde(default)]
+    pub owners: BTreeMap<String, BTreeMap<String, String>>,
     #[serde(default)]
     pub output: OutputConfig,
 }
 
+impl Config {
+    /// Each owner handle, and who they are in the named provider, for the owners mapped in it.
+    pub fn tracker_users(&self, provider: &str) -> HashMap<&str, &str> {
+        self.owners
+            .iter()
+            .filter_map(|(owner, users)| Some((owner.as_str(), users.get(provider)?.as_str())))
+            .collect()
+    }
+}
+
 #[derive(serde::Deserialize, Debug, Default)]
 #[serde(default)]
 pub struct ScanConfig {
     pub ignore: Vec<String>,
     pub include: Vec<String>,
     pub match_case_insensitive: bool,
+    /// Encodings to try, in order, for files that aren't UTF-8 and have no byte order mark
+    pub encodings: Vec<String>,
+}
+
+/// A language added or overridden in `.tudu.yaml`. Anything left out is copied from the
+/// built-in language named by `like`, or the built-in language of the same name.
+#[derive(serde::Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct LanguageConfig {
+    pub extensions: Vec<String>,
+    pub filenames: Vec<String>,
+    pub interpreters: Vec<String>,
+    pub like: Option<String>,
+    pub line_comments: Option<Vec<String>>,
+    pub block_comments: Option<Vec<(String, String)>>,
+    pub nested_comments: Option<bool>,
+    pub comment_needs_space: Option<bool>,
+    pub quotes: Option<Vec<lexer::Quote>>,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct AttributesConfig {
+    /// Report every key that isn't built in or listed in `keys`, not just likely typos
+    pub strict: bool,
+    pub keys: BTreeMap<String, AttributeConfig>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct AttributeConfig {
+    #[serde(rename = "type")]
+    pub kind: AttributeType,
+    /// What an enum's value can be
+    #[serde(default)]
+    pub values: Vec<String>,
+    /// Whether every TODO with a `(...)` must set this key
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AttributeType {
+    Flag,    // bidir, bidir=false
+    Text,    // status="In Progress"
+    Date,    // due=2025-10-01
+    User,    // assignee=@alice
+    Enum,    // priority=high
+    Integer, // estimate=3
+    List,    // labels=urgent,backend
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -68,19 +356,53 @@ pub enum ProviderConfig {
     Notion(NotionConfig),
     Jira(JiraConfig),
     Github(GithubConfig),
+    Gitlab(GitlabConfig),
 }
 
-#[derive(serde::Deserialize, Debug)]
+impl ProviderConfig {
+    pub fn kind(&self) -> ProviderKind {
+        match self {
+            ProviderConfig::Notion(_) => ProviderKind::Notion,
+            ProviderConfig::Jira(_) => ProviderKind::Jira,
+            ProviderConfig::Github(_) => ProviderKind::GitHub,
+            ProviderConfig::Gitlab(_) => ProviderKind::GitLab,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
 pub struct OutputConfig {
-    #[serde(default = "default_format")]
-    pub format: String,
+    #[serde(default)]
+    pub format: Format,
     #[serde(default)]
     pub verbose: bool,
+    #[serde(default)]
+    pub min_severity: Option<Severity>,
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct NotionConfig {
     pub database_id: String,
+    /// The database's title property, which new issues are named with
+    #[serde(default = "default_title_property")]
+    pub title_property: String,
+    /// The database's people property, which new issues are assigned with
+    #[serde(default)]
+    pub assignee_property: Option<String>,
+    /// What the database's IDs look like, e.g. `TASK-\d+`; see [`ids`]. Only
+    /// `<prefix>-<number>` IDs can be checked; others are never reported as unknown
+    #[serde(default)]
+    pub id_pattern: Option<String>,
+    /// The database's status or select property, which tells closed issues apart
+    #[serde(default)]
+    pub status_property: Option<String>,
+    /// The values of `status_property` that mean an issue is closed
+    #[serde(default = "default_closed_statuses")]
+    pub closed_statuses: Vec<String>,
+    /// The properties to set from a `TODO(new)`'s attributes, by attribute key, e.g.
+    /// `labels: Tags`. Attributes without one aren't sent.
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
     // other notion-specific fields
 }
 
@@ -88,37 +410,58 @@ pub struct NotionConfig {
 pub struct JiraConfig {
     pub server: String,
     pub project: String,
+    /// What the project's issue keys look like; defaults to `<project>-\d+`
+    #[serde(default)]
+    pub id_pattern: Option<String>,
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct GithubConfig {
     pub owner: String,
     pub repo: String,
+    /// What the repo's issue references look like; defaults to `#\d+`
+    #[serde(default)]
+    pub id_pattern: Option<String>,
 }
 
-fn default_mode() -> String {
-    "validate".to_string()
+#[derive(serde::Deserialize, Debug)]
+pub struct GitlabConfig {
+    /// The project's path, e.g. `group/subgroup/project`
+    pub project: String,
+    /// What the project's issue references look like; defaults to `#\d+`
+    #[serde(default)]
+    pub id_pattern: Option<String>,
 }
 
-fn default_format() -> String {
-    "standard".to_string()
+fn default_title_property() -> String {
+    "Name".to_string()
 }
 
-impl Default for OutputConfig {
-    fn default() -> Self {
-        Self {
-            format: default_format(),
-            verbose: false,
-        }
-    }
+fn default_closed_statuses() -> Vec<String> {
+    vec!["Done".to_string()]
+}
+
+fn default_mode() -> String {
+    "validate".to_string()
+}
+
+pub fn default_markers() -> BTreeMap<String, Severity> {
+    BTreeMap::from([
+        ("TODO".to_string(), Severity::Warning),
+        ("FIXME".to_string(), Severity::Error),
+    ])
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             scan: ScanConfig::default(),
+            markers: default_markers(),
+            languages: BTreeMap::new(),
+            attributes: AttributesConfig::default(),
             mode: default_mode(),
-            providers: HashMap::new(),
+            providers: BTreeMap::new(),
+            owners: BTreeMap::new(),
             output: OutputConfig::default(),
         }
     }
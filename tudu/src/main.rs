@@ -5,122 +5,513 @@
 ///
 use clap::Parser;
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Mutex;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::{DirEntry, ParallelVisitor, ParallelVisitorBuilder, WalkState};
 
 use tudu::{
-    Args, Config, ProviderConfig, TodoAttributeValue, TodoItem, TodoReference,
-    providers::{IssueProvider, NotionProvider},
+    Args, Command, Config, Format, GroupBy, ProviderConfig, ScanConfig, Severity,
+    TodoAttributeValue, TodoItem, TodoReference,
+    attributes::Schema,
+    canonical, default_markers,
+    encoding::{Decoded, Decoder, Source},
+    error::{TuduError, exit_code},
+    ids::IdPatterns,
+    languages::LanguageTable,
+    lexer::{self, Comment, CommentKind, LineIndex, Syntax},
+    output,
+    parser::{self, Diagnostic, ParsedTodo},
+    providers::{IssueProvider, IssueStatus, NewIssue, NotionProvider, notion::NotionError},
 };
 
+/// `println!`, but through [`print_line`], so output stops quietly once nothing reads it.
+macro_rules! outln {
+    () => {
+        print_line("")
+    };
+    ($($arg:tt)*) => {
+        print_line(&format!($($arg)*))
+    };
+}
+
 #[tokio::main]
 async fn main() {
-    dotenvy::dotenv().ok();
-    match std::env::var("NOTION_TOKEN") {
-        Ok(token) => println!("Found token: {}...", &token[..10]),
-        Err(_) => println!("❌ No NOTION_TOKEN found in environment"),
+    match run().await {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(err.exit_code());
+        }
     }
+}
 
+/// Runs `tudu`, returning the exit code for a run that finished.
+async fn run() -> Result<i32, TuduError> {
+    dotenvy::dotenv().ok();
     let args = Args::parse();
+    let (path, jobs) = match &args.command {
+        Some(Command::Fmt { path, jobs, .. }) => (path, *jobs),
+        None => (
+            args.path
+                .as_ref()
+                .expect("clap requires a path without a subcommand"),
+            args.jobs,
+        ),
+    };
+
+    if !path.exists() {
+        return Err(TuduError::io(path, std::io::ErrorKind::NotFound.into()));
+    }
+
+    let loaded = load_config()?;
+    let config_found = loaded.is_some();
+    let config = loaded.unwrap_or_default();
+    let verbose = resolve_verbose(&args, &config);
+
+    // Notes about the run go to stderr, so they never mix into output that's parsed
+    if verbose {
+        match std::env::var("NOTION_TOKEN") {
+            Ok(_) => eprintln!("Found NOTION_TOKEN"),
+            Err(_) => eprintln!("No NOTION_TOKEN found in environment"),
+        }
+        if config_found {
+            eprintln!("Loaded configuration from '{}'", CONFIG_PATH);
+        } else {
+            eprintln!("No configuration file found. Using defaults.");
+        }
+        eprintln!("Using configuration: {:?}", config);
+    }
+
+    let scanner = Scanner {
+        matcher: TodoMatcher::new(config.scan.match_case_insensitive, &config.markers),
+        languages: LanguageTable::new(&config.languages).map_err(TuduError::Config)?,
+        decoder: Decoder::new(&config.scan.encodings).map_err(TuduError::Config)?,
+        schema: Schema::new(&config.attributes).map_err(TuduError::Config)?,
+        ids: IdPatterns::new(&config.providers).map_err(TuduError::Config)?,
+    };
+
+    let format = resolve_format(&args, &config);
+    let min_severity = resolve_min_severity(&args, &config);
+    let owner = args
+        .owner
+        .as_deref()
+        .map(|owner| owner.strip_prefix('@').unwrap_or(owner));
+    let wanted = |todo: &TodoItem| {
+        min_severity.is_none_or(|min_severity| todo.severity >= min_severity)
+            && owner.is_none_or(|owner| todo.owner.as_deref() == Some(owner))
+    };
+
+    // NDJSON records are written as files are scanned, unless sync mode might still rewrite them
+    let streaming = format == Format::Ndjson && config.mode != "sync" && args.command.is_none();
+    let stream = |todo: &TodoItem| {
+        if wanted(todo) {
+            print_line(&output::ndjson::todo(todo));
+        }
+    };
+
+    let Found {
+        todos: mut all_todos,
+        mut warnings,
+    } = scan_path(
+        path,
+        &config,
+        &scanner,
+        jobs,
+        verbose,
+        streaming.then_some(&stream),
+    )?;
+
+    if let Some(Command::Fmt { check, .. }) = args.command {
+        return format_todos(&scanner, &all_todos, check, warnings);
+    }
 
-    if !args.path.exists() {
-        eprintln!("Error: Path '{}' does not exist.", args.path.display());
-        process::exit(1);
+    if config.mode == "sync"
+        && all_todos
+            .iter()
+            .any(|todo| matches!(todo.references.first(), Some(TodoReference::New { .. })))
+    {
+        let mut providers = config.providers.iter();
+        let (Some((provider_name, provider_config)), None) = (providers.next(), providers.next())
+        else {
+            return Err(TuduError::Config(
+                "sync mode files new issues with the configured provider, so configure exactly one"
+                    .to_string(),
+            ));
+        };
+        match provider_config {
+            ProviderConfig::Notion(notion_config) => {
+                let provider = NotionProvider::new(notion_config).map_err(|e| {
+                    TuduError::Provider(format!("failed to create Notion provider: {}", e))
+                })?;
+                let assignees = config.tracker_users(provider_name);
+                create_new_issues(
+                    &provider,
+                    &assignees,
+                    &scanner,
+                    &mut all_todos,
+                    &mut warnings,
+                )
+                .await?;
+            }
+            other => {
+                return Err(TuduError::Provider(format!(
+                    "filing new issues isn't supported for {} yet",
+                    other.kind()
+                )));
+            }
+        }
+    }
+
+    all_todos.retain(|todo| wanted(todo));
+    let Checked { unknown, closed } =
+        check_references(&config, &scanner, &all_todos, &mut warnings).await;
+
+    for warning in &warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    match format {
+        Format::Standard => {
+            process_results(&all_todos);
+            print_results(&all_todos, verbose, args.group_by);
+            print_diagnostics(&all_todos);
+        }
+        Format::Json => {
+            let report = output::json::report(&all_todos, &unknown, &warnings);
+            print_line(&serde_json::to_string_pretty(&report).unwrap());
+        }
+        Format::Sarif => {
+            let log = output::sarif::report(&all_todos, &unknown, &closed, &warnings);
+            print_line(&serde_json::to_string_pretty(&log).unwrap());
+        }
+        Format::Github => {
+            for command in output::github::annotations(&all_todos, &unknown, &warnings) {
+                print_line(&command);
+            }
+            write_step_summary(&output::github::summary(&all_todos, &unknown));
+        }
+        Format::Ndjson => {
+            if !streaming {
+                for todo in &all_todos {
+                    print_line(&output::ndjson::todo(todo));
+                }
+            }
+            print_line(&output::ndjson::summary(&all_todos, &unknown, &warnings));
+        }
+    }
+
+    if let Some(fail_on) = args.fail_on
+        && all_todos.iter().any(|todo| todo.severity >= fail_on)
+    {
+        return Ok(exit_code::FINDINGS);
+    }
+
+    if config.mode == "validate" && all_todos.iter().any(|todo| !todo.diagnostics.is_empty()) {
+        return Ok(exit_code::FINDINGS);
     }
 
-    let config = load_config();
-    println!("Using configuration: {:?}", config);
+    Ok(exit_code::SUCCESS)
+}
+
+/// Writes a line of output. When whatever reads it has gone away, like `head` after its first
+/// lines, there's no one left to write for, so `tudu` stops quietly instead of panicking.
+fn print_line(line: &str) {
+    let mut stdout = std::io::stdout().lock();
+    if let Err(err) = writeln!(stdout, "{}", line) {
+        if err.kind() == std::io::ErrorKind::BrokenPipe {
+            process::exit(exit_code::SUCCESS);
+        }
+        eprintln!("Error: failed to write output: {}", err);
+        process::exit(exit_code::IO_ERROR);
+    }
+}
 
-    // Create a resizable vector to hold all found TODOs
-    let mut all_todos = Vec::new();
+/// Called with each TODO as soon as its file has been scanned, for output that streams.
+type OnTodo<'a> = &'a (dyn Fn(&TodoItem) + Sync);
 
-    if args.path.is_file() {
+/// Scans `path`, a file or a directory. Files that can't be scanned end up in the warnings
+/// instead of stopping the scan.
+fn scan_path(
+    path: &Path,
+    config: &Config,
+    scanner: &Scanner,
+    jobs: Option<usize>,
+    verbose: bool,
+    on_todo: Option<OnTodo>,
+) -> Result<Found, TuduError> {
+    if path.is_file() {
+        let mut found = Found::default();
         // First notable example of borrowing in Rust:
-        // We pass a reference to args.path (which is owned by args)
-        // and a mutable reference to all_todos (which is owned by main)
-        scan_file(&args.path, &mut all_todos);
-    } else if args.path.is_dir() {
-        scan_directory(&args.path, &mut all_todos);
+        // We pass a reference to path (which is owned by args)
+        // and a mutable reference to found.todos (which is owned by this function)
+        let syntax = scanner.languages.syntax_for(path);
+        match scan_file(path, syntax, scanner, verbose, &mut found.todos) {
+            Ok(()) => {
+                if let Some(on_todo) = on_todo {
+                    found.todos.iter().for_each(on_todo);
+                }
+            }
+            Err(warning) => found.warnings.push(warning),
+        }
+        Ok(found)
+    } else if path.is_dir() {
+        let filter = ScanFilter::new(path, &config.scan)
+            .map_err(|err| TuduError::Config(err.to_string()))?;
+        Ok(scan_directory(
+            path,
+            &filter,
+            scanner,
+            jobs.unwrap_or(0),
+            verbose,
+            on_todo,
+        ))
     } else {
+        Err(TuduError::io(
+            path,
+            std::io::Error::other("neither a file nor a directory"),
+        ))
+    }
+}
+
+/// `tudu fmt`: rewrites every TODO into its canonical form (see `tudu::canonical`), keeping
+/// the comment delimiters and indentation around it. With `check`, nothing is written; the
+/// TODOs that would change are listed instead, and the exit code says whether there were any.
+/// Malformed TODOs are left as they are, since there's no telling what they should say.
+fn format_todos(
+    scanner: &Scanner,
+    todos: &[TodoItem],
+    check: bool,
+    mut warnings: Vec<TuduError>,
+) -> Result<i32, TuduError> {
+    let mut by_file: BTreeMap<&Path, Vec<&TodoItem>> = BTreeMap::new();
+    for todo in todos {
+        by_file.entry(&todo.file_path).or_default().push(todo);
+    }
+
+    let mut changed_todos = 0;
+    let mut changed_files = 0;
+    let mut malformed = 0;
+    for (path, mut file_todos) in by_file {
+        let mut source = match read_for_rewrite(path, scanner) {
+            Ok(source) => source,
+            Err(err) => {
+                warnings.push(err);
+                continue;
+            }
+        };
+
+        file_todos.sort_by_key(|todo| todo.text_span.start);
+        let mut edits = Vec::new();
+        for todo in file_todos {
+            let range = todo.text_span.start..todo.text_span.end;
+            let Some(text) = source.text.get(range.clone()) else {
+                continue;
+            };
+            let Some(marker) = scanner.matcher.marker.find(text).filter(|m| m.start() == 0) else {
+                continue;
+            };
+            match canonical::canonicalize(text, marker.len(), &scanner.ids, &scanner.schema) {
+                Some(canonical) if canonical != text => {
+                    if check {
+                        outln!("{}:{}", path.display(), todo.line_number);
+                        outln!("  - {}", text);
+                        outln!("  + {}", canonical);
+                    }
+                    edits.push((range, canonical));
+                }
+                Some(_) => {}
+                None => {
+                    malformed += 1;
+                    eprintln!(
+                        "Leaving '{}', line {} as it is: it's malformed, or rewriting it would lose something",
+                        path.display(),
+                        todo.line_number
+                    );
+                }
+            }
+        }
+
+        changed_todos += edits.len();
+        if check || edits.is_empty() {
+            continue;
+        }
+        // From the end of the file back, so earlier ranges stay put as later ones are rewritten
+        for (range, canonical) in edits.into_iter().rev() {
+            source.text.replace_range(range, &canonical);
+        }
+        let bytes = match encode_for_rewrite(path, &source) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warnings.push(err);
+                continue;
+            }
+        };
+        fs::write(path, bytes).map_err(|err| TuduError::io(path, err))?;
+        changed_files += 1;
+    }
+
+    for warning in &warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    if malformed > 0 {
         eprintln!(
-            "Error: '{}' is neither a file nor a directory.",
-            args.path.display()
+            "Left {} TODO(s) as they are; `tudu <PATH>` lists what's wrong with them",
+            malformed
         );
-        process::exit(1);
     }
 
-    process_results(&all_todos);
-    // TODO(michaelfromyeg): support different output formats
-    print_results(&all_todos, resolve_verbose(&args, &config));
+    if !check {
+        outln!(
+            "Rewrote {} TODO(s) in {} file(s)",
+            changed_todos,
+            changed_files
+        );
+        Ok(exit_code::SUCCESS)
+    } else if changed_todos > 0 {
+        outln!("{} TODO(s) aren't in the canonical form", changed_todos);
+        Ok(exit_code::FINDINGS)
+    } else {
+        outln!("All TODOs are in the canonical form");
+        Ok(exit_code::SUCCESS)
+    }
+}
+
+/// The references a provider found something wrong with.
+#[derive(Default)]
+struct Checked<'t> {
+    /// References to issues that don't exist
+    unknown: Vec<(&'t TodoItem, &'t TodoReference)>,
+    /// References to issues that have been closed
+    closed: Vec<(&'t TodoItem, &'t TodoReference)>,
+}
 
-    // Test out a Notion provider, if given
-    let tracked_todos: Vec<_> = all_todos
+/// The references the provider named `provider_name` checks. Databases of the same kind are
+/// told apart by their ID patterns. Qualified references like `nt:PAGE_ID` aren't in any one
+/// of them, so the first provider of their kind checks those, and the rest don't check them
+/// again.
+fn provider_references<'t>(
+    providers: &BTreeMap<String, ProviderConfig>,
+    ids: &IdPatterns,
+    provider_name: &str,
+    todos: &'t [TodoItem],
+) -> Vec<(&'t TodoItem, &'t TodoReference)> {
+    let kind = providers[provider_name].kind();
+    let first_of_kind = providers
+        .iter()
+        .find(|(_, provider)| provider.kind() == kind)
+        .map(|(name, _)| name.as_str());
+    todos
         .iter()
-        .filter_map(|todo| match &todo.reference {
-            Some(TodoReference::Tracked(id)) => Some((todo, id)),
-            _ => None,
+        .flat_map(|todo| {
+            todo.references
+                .iter()
+                .map(move |reference| (todo, reference))
         })
-        .collect();
-    // Test notion provider if configured
+        .filter(|(_, reference)| reference.provider() == Some(kind))
+        .filter(|(_, reference)| match reference {
+            TodoReference::Tracked(id) => ids.provider_for(id) == Some(provider_name),
+            _ => first_of_kind == Some(provider_name),
+        })
+        .collect()
+}
+
+/// Checks each tracked TODO with the provider that tracks it, returning the references to
+/// issues that don't exist or are closed. References it couldn't be asked about aren't
+/// returned. This only reads, so a provider that can't be set up or queried, e.g. without a
+/// token, is just a warning.
+async fn check_references<'t>(
+    config: &Config,
+    scanner: &Scanner,
+    todos: &'t [TodoItem],
+    warnings: &mut Vec<TuduError>,
+) -> Checked<'t> {
+    let mut checked = Checked::default();
     for (provider_name, provider_config) in &config.providers {
+        let references = provider_references(&config.providers, &scanner.ids, provider_name, todos);
+
         match provider_config {
             ProviderConfig::Notion(notion_config) => {
                 // println!("Using Notion provider: {}", provider_name);
 
-                match NotionProvider::new(notion_config) {
-                    Ok(provider) => {
-                        for (_todo, id) in &tracked_todos {
-                            match provider.issue_exists(id).await {
-                                Ok(true) => true,
-                                Ok(false) => false,
-                                Err(e) => false,
-                            };
+                let provider = match NotionProvider::new(notion_config) {
+                    Ok(provider) => provider,
+                    Err(e) => {
+                        warnings.push(TuduError::Provider(format!(
+                            "failed to create Notion provider '{}', so its issues weren't checked: {}",
+                            provider_name, e
+                        )));
+                        continue;
+                    }
+                };
+                for (todo, reference) in references {
+                    let status = match reference {
+                        TodoReference::Notion { page_id } => provider.page_status(page_id).await,
+                        TodoReference::Tracked(id) => provider.issue_status(id).await,
+                        _ => continue,
+                    };
+                    match status {
+                        Ok(IssueStatus::Open) => {}
+                        Ok(IssueStatus::Missing) => checked.unknown.push((todo, reference)),
+                        Ok(IssueStatus::Closed) => checked.closed.push((todo, reference)),
+                        Err(e @ NotionError::UnsupportedId(_)) => {
+                            warnings.push(TuduError::Provider(e.to_string()))
+                        }
+                        // Every other query would fail the same way
+                        Err(e) => {
+                            warnings.push(TuduError::Provider(format!(
+                                "failed to query Notion provider '{}', so its issues weren't checked: {}",
+                                provider_name, e
+                            )));
+                            break;
                         }
                     }
-                    Err(e) => println!("Failed to create Notion provider: {}", e),
                 }
             }
             _ => {
-                // Handle other provider types (Jira, Github) when you implement them
-                println!("Skipping unsupported provider type: {}", provider_name);
+                // Handle other provider types (Jira, Github, Gitlab) when you implement them
+                eprintln!("Skipping unsupported provider type: {}", provider_name);
             }
         }
     }
+    checked
+}
+
+/// Adds Markdown to the job's summary page, when running in GitHub Actions.
+fn write_step_summary(markdown: &str) {
+    let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY") else {
+        return;
+    };
+    // Other steps write to the same file, so add to it rather than replacing it
+    let written = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(markdown.as_bytes()));
+    if let Err(err) = written {
+        eprintln!("Warning: {}", TuduError::io(path, err));
+    }
 }
 
-fn load_config() -> Config {
-    let config_path = Path::new(".tudu.yaml");
+const CONFIG_PATH: &str = ".tudu.yaml";
+
+/// The configuration in `.tudu.yaml`, or `None` if there isn't one.
+fn load_config() -> Result<Option<Config>, TuduError> {
+    let config_path = Path::new(CONFIG_PATH);
 
     if config_path.exists() && config_path.is_file() {
-        match fs::read_to_string(config_path) {
-            Ok(contents) => match serde_yaml::from_str::<Config>(&contents) {
-                Ok(config) => {
-                    println!("Loaded configuration from '{}'", config_path.display());
-                    config
-                }
-                Err(err) => {
-                    eprintln!(
-                        "Error parsing config file '{}': {}",
-                        config_path.display(),
-                        err
-                    );
-                    process::exit(1);
-                }
-            },
-            Err(err) => {
-                eprintln!(
-                    "Error reading config file '{}': {}",
-                    config_path.display(),
-                    err
-                );
-                process::exit(1);
-            }
-        }
+        let contents =
+            fs::read_to_string(config_path).map_err(|err| TuduError::io(config_path, err))?;
+        let config = serde_yaml::from_str::<Config>(&contents)
+            .map_err(|err| TuduError::Config(format!("'{}': {}", config_path.display(), err)))?;
+        Ok(Some(config))
     } else {
-        println!("No configuration file found. Using defaults.");
-        Config::default()
+        Ok(None)
     }
 }
 
@@ -128,342 +519,1171 @@ fn resolve_verbose(args: &Args, config: &Config) -> bool {
     args.verbose || config.output.verbose
 }
 
-fn resolve_format(args: &Args, config: &Config) -> String {
-    args.format.clone().unwrap_or(config.output.format.clone())
+fn resolve_min_severity(args: &Args, config: &Config) -> Option<Severity> {
+    args.min_severity.or(config.output.min_severity)
+}
+
+fn resolve_format(args: &Args, config: &Config) -> Format {
+    args.format.unwrap_or(config.output.format)
+}
+
+/// What files are scanned with. Built once from `Config` and shared by every walker thread.
+#[derive(Default)]
+struct Scanner {
+    matcher: TodoMatcher,
+    languages: LanguageTable,
+    decoder: Decoder,
+    schema: Schema,
+    ids: IdPatterns,
+}
+
+/// Scans a file written in `syntax`, which the caller has already worked out, since that can
+/// mean reading the start of the file for a shebang.
+fn scan_file(
+    file_path: &Path,
+    syntax: &Syntax,
+    scanner: &Scanner,
+    verbose: bool,
+    todos: &mut Vec<TodoItem>,
+) -> Result<(), TuduError> {
+    let bytes = fs::read(file_path).map_err(|err| TuduError::io(file_path, err))?;
+
+    let contents = match scanner.decoder.decode(&bytes) {
+        Decoded::Text(contents) => contents,
+        Decoded::Binary => {
+            if verbose {
+                eprintln!("Skipping '{}': {}", file_path.display(), SkipReason::Binary);
+            }
+            return Ok(());
+        }
+        Decoded::Unknown => {
+            return Err(TuduError::UnknownEncoding {
+                path: file_path.to_path_buf(),
+            });
+        }
+    };
+
+    find_todos_in_content(&contents, file_path, syntax, scanner, todos);
+    Ok(())
+}
+
+/// A file's text as it is on disk, for rewriting TODOs in place. It's decoded the way it was
+/// scanned, so the TODOs' spans are spans into it too.
+fn read_for_rewrite(path: &Path, scanner: &Scanner) -> Result<Source, TuduError> {
+    let bytes = fs::read(path).map_err(|err| TuduError::io(path, err))?;
+    scanner
+        .decoder
+        .decode_source(&bytes)
+        .map_err(|_| TuduError::Rewrite {
+            path: path.to_path_buf(),
+            reason: "it's no longer text in any encoding in scan.encodings".to_string(),
+        })
+}
+
+/// The bytes to write back for a rewritten file, in the encoding it was read in.
+fn encode_for_rewrite(path: &Path, source: &Source) -> Result<Vec<u8>, TuduError> {
+    source.encode().ok_or_else(|| TuduError::Rewrite {
+        path: path.to_path_buf(),
+        reason: format!(
+            "it can't be written back as {} without losing something",
+            source.encoding()
+        ),
+    })
 }
 
-fn scan_file(file_path: &Path, todos: &mut Vec<TodoItem>) {
-    // `unwrap_or` is used here because file paths can be non-UTF-8
-    // on some systems. In that case, we just use "unknown file". (We don't throw.)
-    let filename = file_path.to_str().unwrap_or("unknown file");
+/// Files an issue for every `TODO(new)`, and rewrites each comment in place to reference it:
+/// `TODO(new="Add retries", labels=net)` becomes `TODO(TASK-12, labels=net)`. Rewritten files
+/// are scanned again, so the TODOs' spans and references match what's now on disk.
+/// Issues are assigned to the provider's user for the TODO's owner, if `assignees` has one.
+async fn create_new_issues<P: IssueProvider>(
+    provider: &P,
+    assignees: &HashMap<&str, &str>,
+    scanner: &Scanner,
+    todos: &mut Vec<TodoItem>,
+    warnings: &mut Vec<TuduError>,
+) -> Result<(), TuduError> {
+    let mut by_file: BTreeMap<PathBuf, Vec<&TodoItem>> = BTreeMap::new();
+    for todo in todos.iter() {
+        if matches!(todo.references.first(), Some(TodoReference::New { .. })) {
+            by_file
+                .entry(todo.file_path.clone())
+                .or_default()
+                .push(todo);
+        }
+    }
+
+    let mut rewritten = Vec::new();
+    let mut failure = None;
+    for (path, mut new_todos) in by_file {
+        let mut source = match read_for_rewrite(&path, scanner) {
+            Ok(source) => source,
+            Err(err) => {
+                warnings.push(err);
+                continue;
+            }
+        };
+        let mut changed = false;
+
+        // From the end of the file back, so earlier spans stay put as later ones are rewritten
+        new_todos.sort_by_key(|todo| std::cmp::Reverse(todo.comment_span.start));
+        for todo in new_todos {
+            let Some(TodoReference::New { title }) = todo.references.first() else {
+                continue;
+            };
+            let Some(span) = todo.reference_span else {
+                continue;
+            };
+            let span = span.start..span.end;
+            let Some(new) = source
+                .text
+                .get(span.clone())
+                .and_then(parser::find_new_reference)
+            else {
+                warnings.push(TuduError::Rewrite {
+                    path: path.clone(),
+                    reason: format!("line {} changed since it was scanned", todo.line_number),
+                });
+                continue;
+            };
+
+            let title = title
+                .clone()
+                .unwrap_or_else(|| todo.message.lines().next().unwrap_or("").to_string());
+            if title.is_empty() {
+                warnings.push(TuduError::Rewrite {
+                    path: path.clone(),
+                    reason: format!(
+                        "the TODO on line {} has no title or message to name its issue with",
+                        todo.line_number
+                    ),
+                });
+                continue;
+            }
 
-    // We then read the file, and match on various error types
-    let contents = match fs::read_to_string(filename) {
-        Ok(contents) => contents,
-        Err(error) => {
-            match error.kind() {
-                std::io::ErrorKind::NotFound => {
-                    eprintln!("File '{}' not found", filename);
+            let issue = NewIssue {
+                title,
+                assignee: todo
+                    .owner
+                    .as_deref()
+                    .and_then(|owner| assignees.get(owner))
+                    .map(|user| user.to_string()),
+                attributes: todo.attributes.clone().unwrap_or_default(),
+            };
+            match provider.create_issue(&issue).await {
+                Ok(id) => {
+                    eprintln!("Created {} for {}:{}", id, path.display(), todo.line_number);
+                    source
+                        .text
+                        .replace_range(span.start + new.start..span.start + new.end, &id);
+                    changed = true;
                 }
-                std::io::ErrorKind::PermissionDenied => {
-                    eprintln!("Permission denied reading '{}'", filename);
+                Err(err) => {
+                    failure = Some(TuduError::Provider(format!(
+                        "failed to create an issue for {}:{}: {}",
+                        path.display(),
+                        todo.line_number,
+                        err
+                    )));
+                    break;
                 }
-                _ => {
-                    eprintln!("Error reading file '{}': {}", filename, error);
+            }
+        }
+
+        // Even after a failure, keep the references to the issues that were filed
+        if changed {
+            match encode_for_rewrite(&path, &source) {
+                Ok(bytes) => {
+                    fs::write(&path, bytes).map_err(|err| TuduError::io(&path, err))?;
+                    rewritten.push(path);
                 }
+                Err(err) => warnings.push(err),
             }
-            process::exit(1);
         }
-    };
+        if failure.is_some() {
+            break;
+        }
+    }
+
+    for path in rewritten {
+        todos.retain(|todo| todo.file_path != path);
+        let syntax = scanner.languages.syntax_for(&path);
+        scan_file(&path, syntax, scanner, false, todos)?;
+    }
+    todos.sort_by(|a, b| (&a.file_path, a.line_number).cmp(&(&b.file_path, b.line_number)));
 
-    find_todos_in_content(&contents, file_path, todos);
+    failure.map_or(Ok(()), Err)
 }
 
-fn scan_directory(dir_path: &Path, todos: &mut Vec<TodoItem>) {
+#[cfg(test)]
+mod provider_references_tests {
+    use super::*;
+
+    #[test]
+    fn each_reference_is_checked_once() {
+        let providers: BTreeMap<String, ProviderConfig> = serde_yaml::from_str(
+            "bugs: { type: notion, database_id: b, id_pattern: 'BUG-\\d+' }\n\
+             tasks: { type: notion, database_id: t, id_pattern: 'TASK-\\d+' }\n",
+        )
+        .unwrap();
+        let scanner = Scanner {
+            ids: IdPatterns::new(&providers).unwrap(),
+            ..Scanner::default()
+        };
+        let mut todos = Vec::new();
+        find_todos_in_content(
+            "// TODO(BUG-1): a\n\
+             // TODO(TASK-2): b\n\
+             // TODO(nt:0123456789abcdef0123456789abcdef): c\n",
+            Path::new("lib.rs"),
+            scanner.languages.syntax_for(Path::new("lib.rs")),
+            &scanner,
+            &mut todos,
+        );
+
+        let issues = |provider_name: &str| -> Vec<String> {
+            provider_references(&providers, &scanner.ids, provider_name, &todos)
+                .into_iter()
+                .filter_map(|(_, reference)| reference.issue())
+                .collect()
+        };
+        assert_eq!(
+            issues("bugs"),
+            ["BUG-1", "nt:0123456789abcdef0123456789abcdef"]
+        );
+        assert_eq!(issues("tasks"), ["TASK-2"]);
+    }
+}
+
+#[cfg(test)]
+mod create_new_issues_tests {
+    use super::*;
+
+    /// Hands out `TASK-1`, `TASK-2`, ... and remembers what it was asked to file.
+    #[derive(Default)]
+    struct FakeProvider {
+        created: Mutex<Vec<NewIssue>>,
+    }
+
+    #[async_trait::async_trait]
+    impl IssueProvider for FakeProvider {
+        type Error = std::io::Error;
+
+        async fn issue_exists(&self, _id: &str) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+
+        async fn create_issue(&self, issue: &NewIssue) -> Result<String, Self::Error> {
+            let mut created = self.created.lock().unwrap();
+            created.push(issue.clone());
+            Ok(format!("TASK-{}", created.len()))
+        }
+    }
+
+    #[tokio::test]
+    async fn files_issues_and_rewrites_references() {
+        let dir = std::env::temp_dir().join(format!("tudu-new-issues-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lib.rs");
+        fs::write(
+            &path,
+            "fn f() {} // TODO(@alice, new): add retries\r\n\
+             // TODO(new=\"Handle (rare) errors\", labels=net,io, bidir): see #12\r\n\
+             // TODO(TASK-9): already tracked\r\n",
+        )
+        .unwrap();
+        // Spans skip the byte order mark, which is still in the file that's rewritten
+        let bom_path = dir.join("utf8-bom.rs");
+        fs::write(&bom_path, "\u{feff}// TODO(new): with a BOM\n").unwrap();
+        // Written back in the encoding it was read in
+        let utf16 = |text: &str| -> Vec<u8> {
+            let units = text.encode_utf16().flat_map(u16::to_le_bytes);
+            b"\xFF\xFE".iter().copied().chain(units).collect()
+        };
+        let utf16_path = dir.join("utf16-bom.rs");
+        fs::write(&utf16_path, utf16("// TODO(new): r\u{e9}sum\u{e9}\r\n")).unwrap();
+
+        let scanner = Scanner::default();
+        let mut todos = Vec::new();
+        for path in [&path, &bom_path, &utf16_path] {
+            let syntax = scanner.languages.syntax_for(path);
+            scan_file(path, syntax, &scanner, false, &mut todos).unwrap();
+        }
+        let provider = FakeProvider::default();
+        let mut warnings = Vec::new();
+        let assignees = HashMap::from([("alice", "user-1")]);
+        create_new_issues(&provider, &assignees, &scanner, &mut todos, &mut warnings)
+            .await
+            .unwrap();
+
+        assert!(warnings.is_empty());
+        // Filed from the bottom of the file up
+        let created = provider.created.lock().unwrap();
+        assert_eq!(created[0].title, "Handle (rare) errors");
+        assert_eq!(
+            created[0].attributes["labels"],
+            TodoAttributeValue::List(vec!["net".to_string(), "io".to_string()])
+        );
+        assert_eq!(created[0].assignee, None);
+        assert_eq!(created[1].title, "add retries");
+        // Assigned to whoever owns the TODO
+        assert_eq!(created[1].assignee.as_deref(), Some("user-1"));
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "fn f() {} // TODO(@alice, TASK-2): add retries\r\n\
+             // TODO(TASK-1, labels=net,io, bidir): see #12\r\n\
+             // TODO(TASK-9): already tracked\r\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&bom_path).unwrap(),
+            "\u{feff}// TODO(TASK-4): with a BOM\n"
+        );
+        assert_eq!(
+            fs::read(&utf16_path).unwrap(),
+            utf16("// TODO(TASK-3): r\u{e9}sum\u{e9}\r\n")
+        );
+        let references: Vec<_> = todos.iter().map(|todo| todo.references.clone()).collect();
+        assert_eq!(
+            references,
+            ["TASK-2", "TASK-1", "TASK-9", "TASK-3", "TASK-4"]
+                .map(|id| vec![TodoReference::Tracked(id.to_string())])
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+fn scan_directory(
+    dir_path: &Path,
+    filter: &ScanFilter,
+    scanner: &Scanner,
+    jobs: usize,
+    verbose: bool,
+    on_todo: Option<OnTodo>,
+) -> Found {
     // By default, this includes gitignore rules
+    // A thread count of 0 lets `ignore` pick one based on the number of CPUs
     let walker = ignore::WalkBuilder::new(dir_path)
         .add_custom_ignore_filename(".tuduignore")
-        .build();
+        .threads(jobs)
+        .build_parallel();
+
+    let found = Mutex::new(Found::default());
+    walker.visit(&mut TodoCollectorBuilder {
+        dir_path,
+        filter,
+        scanner,
+        verbose,
+        on_todo,
+        found: &found,
+    });
 
-    for result in walker {
+    // Threads finish in any order, so sort to keep the output stable
+    let mut found = found.into_inner().unwrap();
+    found
+        .todos
+        .sort_by(|a, b| (&a.file_path, a.line_number).cmp(&(&b.file_path, b.line_number)));
+    found.warnings.sort_by(|a, b| a.path().cmp(&b.path()));
+    found
+}
+
+/// What the walker threads have found so far.
+#[derive(Default)]
+struct Found {
+    todos: Vec<TodoItem>,
+    warnings: Vec<TuduError>,
+}
+
+/// Hands out one `TodoCollector` per walker thread.
+struct TodoCollectorBuilder<'s> {
+    dir_path: &'s Path,
+    filter: &'s ScanFilter,
+    scanner: &'s Scanner,
+    verbose: bool,
+    on_todo: Option<OnTodo<'s>>,
+    found: &'s Mutex<Found>,
+}
+
+impl<'s> ParallelVisitorBuilder<'s> for TodoCollectorBuilder<'s> {
+    fn build(&mut self) -> Box<dyn ParallelVisitor + 's> {
+        Box::new(TodoCollector {
+            dir_path: self.dir_path,
+            filter: self.filter,
+            scanner: self.scanner,
+            verbose: self.verbose,
+            on_todo: self.on_todo,
+            local: Found::default(),
+            found: self.found,
+        })
+    }
+}
+
+/// Scans files on a single walker thread, buffering what it finds locally so the
+/// shared list is only locked once, when the thread is done. Streamed output is handed each
+/// file's TODOs right away, though.
+struct TodoCollector<'s> {
+    dir_path: &'s Path,
+    filter: &'s ScanFilter,
+    scanner: &'s Scanner,
+    verbose: bool,
+    on_todo: Option<OnTodo<'s>>,
+    local: Found,
+    found: &'s Mutex<Found>,
+}
+
+impl ParallelVisitor for TodoCollector<'_> {
+    fn visit(&mut self, result: Result<DirEntry, ignore::Error>) -> WalkState {
         let entry = match result {
             Ok(entry) => entry,
             Err(error) => {
-                eprintln!(
-                    "Error reading directory '{}': {}",
-                    dir_path.display(),
-                    error
-                );
-                continue;
+                let message = error.to_string();
+                let source = error
+                    .into_io_error()
+                    .unwrap_or_else(|| std::io::Error::other(message));
+                self.local
+                    .warnings
+                    .push(TuduError::io(self.dir_path, source));
+                return WalkState::Continue;
             }
         };
 
+        // The root itself is never filtered, only what's inside it
+        if entry.depth() > 0 {
+            let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+            if let Some(reason) = self.filter.skip_reason(entry.path(), is_dir) {
+                if self.verbose {
+                    eprintln!("Skipping '{}': {}", entry.path().display(), reason);
+                }
+                // Skipping a directory also skips everything below it
+                return WalkState::Skip;
+            }
+        }
+
         // `is_some_and` here is a nice way to combine an Option check and a predicate (is_file)
-        if entry.file_type().is_some_and(|ft| ft.is_file()) && should_scan_file(entry.path()) {
-            scan_file(entry.path(), todos);
-        }
-    }
-}
-
-fn should_scan_file(path: &Path) -> bool {
-    // `and_then` is used to chain Option-returning calls
-    // (similar to `is_some_and`, but for mapping)
-    path.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| {
-            matches!(
-                ext,
-                "rs" | "py"
-                    | "js"
-                    | "ts"
-                    | "java"
-                    | "cpp"
-                    | "c"
-                    | "h"
-                    | "go"
-                    | "rb"
-                    | "php"
-                    | "swift"
-                    | "kt"
-                    | "scala"
-                    | "cs"
-                    | "sh"
-                    | "bash"
-                    | "zsh"
-                    | "yaml"
-                    | "yml"
-                    | "toml"
-                    | "md"
-                    | "html"
-                    | "css"
-                    | "scss"
-                    | "less"
-            )
-        })
-        // `unwrap` here to catch files without an extension
-        .unwrap_or(false)
+        // Files in languages we don't know aren't scanned
+        if entry.file_type().is_some_and(|ft| ft.is_file())
+            && let Some(language) = self.scanner.languages.detect(entry.path())
+        {
+            let scanned = self.local.todos.len();
+            match scan_file(
+                entry.path(),
+                &language.syntax,
+                self.scanner,
+                self.verbose,
+                &mut self.local.todos,
+            ) {
+                Ok(()) => {
+                    if let Some(on_todo) = self.on_todo {
+                        self.local.todos[scanned..].iter().for_each(on_todo);
+                    }
+                }
+                Err(warning) => self.local.warnings.push(warning),
+            }
+        }
+
+        WalkState::Continue
+    }
 }
 
-fn find_todos_in_content(contents: &str, file_path: &Path, todos: &mut Vec<TodoItem>) {
-    // This matches
-    // - // TODO
-    // - /* TODO */
-    // - # TODO
-    // - <!-- TODO
-    let todo_regex = Regex::new(r"(?i)(//|/\*|#|<!--)\s*(TODO|FIXME).*").unwrap();
+impl Drop for TodoCollector<'_> {
+    fn drop(&mut self) {
+        let mut found = self.found.lock().unwrap();
+        found.todos.append(&mut self.local.todos);
+        found.warnings.append(&mut self.local.warnings);
+    }
+}
 
-    for (line_number, line) in contents.lines().enumerate() {
-        if todo_regex.is_match(line) {
-            todos.push(TodoItem {
-                file_path: file_path.to_path_buf(),
-                line_number: line_number + 1,
-                line_content: line.trim().to_string(),
-                reference: parse_todo_reference(line),
-                attributes: parse_todo_attributes(line),
-            });
+/// The `scan.ignore` and `scan.include` globs from `.tudu.yaml`, applied on top of
+/// `.gitignore` and `.tuduignore`. Both use gitignore syntax, relative to the scan root.
+struct ScanFilter {
+    ignore: Gitignore,
+    // `None` when no include globs are configured, meaning everything is included
+    include: Option<Gitignore>,
+}
+
+#[derive(Debug, PartialEq)]
+enum SkipReason {
+    Ignored(String),
+    NotIncluded,
+    Binary,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Ignored(glob) => write!(f, "matches scan.ignore pattern '{}'", glob),
+            SkipReason::NotIncluded => write!(f, "does not match any scan.include pattern"),
+            SkipReason::Binary => write!(f, "looks like a binary file"),
         }
     }
 }
 
-fn parse_todo_reference(line: &str) -> Option<TodoReference> {
-    if !line.contains("TODO") && !line.contains("FIXME") {
-        return None;
+impl ScanFilter {
+    fn new(root: &Path, config: &ScanConfig) -> Result<Self, ignore::Error> {
+        let ignore = build_globs(root, &config.ignore)?;
+        let include = if config.include.is_empty() {
+            None
+        } else {
+            Some(build_globs(root, &config.include)?)
+        };
+
+        Ok(Self { ignore, include })
     }
 
-    // Check if it has parentheses
-    if let Some(open_paren) = line.find('(')
-        && let Some(close_paren) = line.find(')')
-    {
-        // Make sure ) comes after (
-        if close_paren > open_paren {
-            let inside = &line[open_paren + 1..close_paren].trim();
-
-            // Check for comma (attributes present)
-            if let Some(comma_pos) = inside.find(',') {
-                let id_part = &inside[..comma_pos].trim();
-                if is_valid_id(id_part) {
-                    // This is how we build a Tracked variant of the enum!
-                    // `Some` is used because the return type is Option<TodoReference>
-                    return Some(TodoReference::Tracked(id_part.to_string()));
-                }
-            } else if is_valid_id(inside) {
-                // Just an ID, no attributes
-                return Some(TodoReference::Tracked(inside.to_string()));
-            }
+    /// Explains why `path` should be skipped, or returns `None` if it should be scanned.
+    fn skip_reason(&self, path: &Path, is_dir: bool) -> Option<SkipReason> {
+        if let ignore::Match::Ignore(glob) = self.ignore.matched_path_or_any_parents(path, is_dir) {
+            return Some(SkipReason::Ignored(glob.original().to_string()));
+        }
 
-            // Has parens but not a valid ID - treat as untracked
-            // This handles TODO(john), TODO(wip), etc.
-            return Some(TodoReference::Untracked);
+        // Directories are always walked, since an include glob may match something inside them
+        if let Some(include) = &self.include
+            && !is_dir
+            && !include
+                .matched_path_or_any_parents(path, is_dir)
+                .is_ignore()
+        {
+            return Some(SkipReason::NotIncluded);
         }
+
+        None
     }
+}
 
-    Some(TodoReference::Untracked)
+fn build_globs(root: &Path, globs: &[String]) -> Result<Gitignore, ignore::Error> {
+    let mut builder = GitignoreBuilder::new(root);
+    for glob in globs {
+        builder.add_line(None, glob)?;
+    }
+    builder.build()
 }
 
 #[cfg(test)]
-mod parse_todo_reference_tests {
+mod scan_filter_tests {
     use super::*;
 
+    fn filter(ignore: &[&str], include: &[&str]) -> ScanFilter {
+        let config = ScanConfig {
+            ignore: ignore.iter().map(|s| s.to_string()).collect(),
+            include: include.iter().map(|s| s.to_string()).collect(),
+            ..ScanConfig::default()
+        };
+        ScanFilter::new(Path::new("repo"), &config).unwrap()
+    }
+
     #[test]
-    fn test_parse_untracked() {
-        let line = "// TODO: Refactor this function";
-        let result = parse_todo_reference(line);
-        match result {
-            Some(TodoReference::Untracked) => {}
-            _ => panic!("Expected Untracked"),
-        }
+    fn empty_config_skips_nothing() {
+        let filter = filter(&[], &[]);
+        assert_eq!(
+            filter.skip_reason(Path::new("repo/src/main.rs"), false),
+            None
+        );
     }
 
     #[test]
-    fn test_parse_tracked() {
-        let line = "// TODO(BUG-123): Refactor this function";
-        let result = parse_todo_reference(line);
-        match result {
-            Some(TodoReference::Tracked(id)) => {
-                assert_eq!(id, "BUG-123");
-            }
-            _ => panic!("Expected Tracked with ID BUG-123"),
-        }
+    fn ignore_globs() {
+        let filter = filter(&["target/", "*.min.js"], &[]);
+
+        assert_eq!(
+            filter.skip_reason(Path::new("repo/target"), true),
+            Some(SkipReason::Ignored("target/".to_string()))
+        );
+        assert_eq!(
+            filter.skip_reason(Path::new("repo/web/app.min.js"), false),
+            Some(SkipReason::Ignored("*.min.js".to_string()))
+        );
+        assert_eq!(
+            filter.skip_reason(Path::new("repo/web/app.js"), false),
+            None
+        );
     }
 
     #[test]
-    fn test_parse_with_attributes() {
-        let line = "// TODO(TASK-123, bidir): Implement feature";
-        let result = parse_todo_reference(line);
-        match result {
-            Some(TodoReference::Tracked(id)) => {
-                assert_eq!(id, "TASK-123");
-            }
-            _ => panic!("Expected Tracked with ID TASK-123"),
-        }
+    fn ignore_negation() {
+        let filter = filter(&["vendor/**", "!vendor/ours.rs"], &[]);
+
+        assert!(
+            filter
+                .skip_reason(Path::new("repo/vendor/theirs.rs"), false)
+                .is_some()
+        );
+        assert_eq!(
+            filter.skip_reason(Path::new("repo/vendor/ours.rs"), false),
+            None
+        );
     }
 
     #[test]
-    fn test_parse_empty_parens() {
-        let line = "// TODO(): Empty parens";
-        let result = parse_todo_reference(line);
-        assert!(matches!(result, Some(TodoReference::Untracked)));
+    fn include_allowlist() {
+        let filter = filter(&[], &["src/", "*.py"]);
+
+        assert_eq!(
+            filter.skip_reason(Path::new("repo/src/lib/mod.rs"), false),
+            None
+        );
+        assert_eq!(
+            filter.skip_reason(Path::new("repo/scripts/run.py"), false),
+            None
+        );
+        assert_eq!(
+            filter.skip_reason(Path::new("repo/scripts/run.sh"), false),
+            Some(SkipReason::NotIncluded)
+        );
+        // Directories are walked even when they aren't included themselves
+        assert_eq!(filter.skip_reason(Path::new("repo/scripts"), true), None);
     }
 
     #[test]
-    fn test_parse_person_name() {
-        let line = "// TODO(alice): Review this";
-        let result = parse_todo_reference(line);
-        assert!(matches!(result, Some(TodoReference::Untracked)));
+    fn ignore_wins_over_include() {
+        let filter = filter(&["src/generated/"], &["src/"]);
+
+        assert!(
+            filter
+                .skip_reason(Path::new("repo/src/generated/api.rs"), false)
+                .is_some()
+        );
+    }
+}
+
+/// Recognizes the configured markers (TODO, FIXME, ...). Compiled once from `Config`, and shared
+/// by the comment scanner and the reference/attribute parsers so they agree on case sensitivity.
+struct TodoMatcher {
+    leading: Regex,
+    marker: Regex,
+    markers: BTreeMap<String, Severity>,
+    case_insensitive: bool,
+}
+
+impl TodoMatcher {
+    fn new(case_insensitive: bool, markers: &BTreeMap<String, Severity>) -> Self {
+        let flags = if case_insensitive { "(?i)" } else { "" };
+
+        // Longest first, so a marker is never shadowed by a shorter one it starts with
+        let mut keywords: Vec<&String> = markers.keys().collect();
+        keywords.sort_by_key(|keyword| std::cmp::Reverse(keyword.len()));
+        let alternation = if keywords.is_empty() {
+            // Matches nothing
+            r"[^\s\S]".to_string()
+        } else {
+            keywords
+                .iter()
+                .map(|keyword| marker_pattern(keyword))
+                .collect::<Vec<_>>()
+                .join("|")
+        };
+
+        Self {
+            leading: Regex::new(&format!(r"{}^(?:{})", flags, alternation)).unwrap(),
+            marker: Regex::new(&format!(r"{}(?:{})", flags, alternation)).unwrap(),
+            markers: markers.clone(),
+            case_insensitive,
+        }
+    }
+
+    /// Whether a line of comment text starts with a marker.
+    fn starts_with_marker(&self, comment_text: &str) -> bool {
+        self.leading.is_match(comment_text)
+    }
+
+    /// The marker a line of comment text starts with, as written in the config, and its severity.
+    fn leading_marker(&self, comment_text: &str) -> Option<(&str, Severity)> {
+        let found = self.leading.find(comment_text)?.as_str();
+        self.markers
+            .iter()
+            .find(|(keyword, _)| {
+                *keyword == found || (self.case_insensitive && keyword.eq_ignore_ascii_case(found))
+            })
+            .map(|(keyword, severity)| (keyword.as_str(), *severity))
+    }
+
+    /// Whether `line` mentions a marker anywhere.
+    fn has_marker(&self, line: &str) -> bool {
+        self.marker.is_match(line)
+    }
+}
+
+impl Default for TodoMatcher {
+    fn default() -> Self {
+        Self::new(false, &default_markers())
     }
 }
 
-fn is_valid_id(s: &str) -> bool {
-    // Something of the form ABC-123 (at least one letter, a dash, at least one digit)
-    // TODO(michaelfromyeg): stop compiling regex every time
-    let id_regex = Regex::new(r"^[A-Z]+-\d+$").unwrap();
+/// A regex for one marker keyword, with word boundaries so `// TODOS` or `// todoist` don't match.
+/// Markers like `@todo` start with punctuation, so they need a non-word boundary instead.
+fn marker_pattern(keyword: &str) -> String {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let before = if is_word(keyword.chars().next()) {
+        r"\b"
+    } else {
+        r"\B"
+    };
+    let after = if is_word(keyword.chars().last()) {
+        r"\b"
+    } else {
+        ""
+    };
 
-    // Notice how we don't have to write `return` here - Rust automatically returns the last expression
-    id_regex.is_match(s)
+    format!("{}{}{}", before, regex::escape(keyword), after)
 }
 
 #[cfg(test)]
-mod is_valid_id_tests {
+mod todo_matcher_tests {
     use super::*;
 
     #[test]
-    fn test_valid_ids() {
-        let valid_ids = vec!["TASK-1", "BUG-123", "FEATURE-4567"];
-        for id in valid_ids {
-            assert!(is_valid_id(id), "Expected '{}' to be valid", id);
-        }
+    fn case_sensitive_by_default() {
+        let matcher = TodoMatcher::default();
+        assert!(matcher.starts_with_marker("TODO: upper"));
+        assert!(!matcher.starts_with_marker("todo: lower"));
+        assert!(!matcher.has_marker("// todo: lower"));
     }
 
     #[test]
-    fn test_invalid_ids() {
-        let invalid_ids = vec!["task-1", "BUG123", "FEATURE_", "123-ABC", "BUG-"];
-        for id in invalid_ids {
-            assert!(!is_valid_id(id), "Expected '{}' to be invalid", id);
-        }
+    fn case_insensitive() {
+        let matcher = TodoMatcher::new(true, &default_markers());
+        assert!(matcher.starts_with_marker("todo: lower"));
+        assert!(matcher.starts_with_marker("Fixme(BUG-1): mixed"));
+        assert!(matcher.has_marker("// todo: lower"));
     }
-}
 
-fn parse_todo_attributes(line: &str) -> Option<HashMap<String, TodoAttributeValue>> {
-    if !line.contains("TODO") && !line.contains("FIXME") {
-        return None;
+    #[test]
+    fn word_boundaries() {
+        let matcher = TodoMatcher::new(true, &default_markers());
+        assert!(!matcher.starts_with_marker("todos list"));
+        assert!(!matcher.starts_with_marker("TODOIST integration"));
+        assert!(!matcher.starts_with_marker("fixmenot"));
+        assert!(matcher.starts_with_marker("TODO(TASK-1): still found"));
+        assert!(matcher.starts_with_marker("TODO */"));
     }
 
-    // Check if it has parentheses
-    if let Some(open_paren) = line.find('(')
-        && let Some(close_paren) = line.find(')')
-    {
-        // Make sure ) comes after (
-        if close_paren > open_paren {
-            let inside = &line[open_paren + 1..close_paren].trim();
+    fn custom_markers() -> BTreeMap<String, Severity> {
+        BTreeMap::from([
+            ("TODO".to_string(), Severity::Warning),
+            ("TODOC".to_string(), Severity::Info),
+            ("HACK".to_string(), Severity::Error),
+            ("@todo".to_string(), Severity::Warning),
+        ])
+    }
+
+    #[test]
+    fn configured_markers_and_severities() {
+        let matcher = TodoMatcher::new(false, &custom_markers());
+
+        assert_eq!(
+            matcher.leading_marker("HACK: works around a bug"),
+            Some(("HACK", Severity::Error))
+        );
+        assert_eq!(
+            matcher.leading_marker("TODOC: write the docs"),
+            Some(("TODOC", Severity::Info))
+        );
+        assert_eq!(
+            matcher.leading_marker("@todo handle errors"),
+            Some(("@todo", Severity::Warning))
+        );
+        // FIXME isn't configured here
+        assert_eq!(matcher.leading_marker("FIXME: not a marker"), None);
+    }
+
+    #[test]
+    fn punctuated_marker_boundaries() {
+        let matcher = TodoMatcher::new(false, &custom_markers());
+        assert!(matcher.has_marker("/** @todo later */"));
+        assert!(!matcher.has_marker("mail me at me@todos.dev"));
+        assert!(!matcher.has_marker("user@todo"));
+    }
+
+    #[test]
+    fn case_insensitive_lookup_returns_configured_spelling() {
+        let matcher = TodoMatcher::new(true, &custom_markers());
+        assert_eq!(
+            matcher.leading_marker("hack: lowercase"),
+            Some(("HACK", Severity::Error))
+        );
+    }
+
+    #[test]
+    fn no_markers() {
+        let matcher = TodoMatcher::new(false, &BTreeMap::new());
+        assert!(!matcher.starts_with_marker("TODO: nothing configured"));
+        assert!(!matcher.has_marker("anything"));
+    }
+}
 
-            // Check for comma (attributes present), skip the ID portion
-            if let Some(comma_pos) = inside.find(',') {
-                return Some(parse_attributes_from_string(inside[comma_pos + 1..].trim()));
+fn find_todos_in_content(
+    contents: &str,
+    file_path: &Path,
+    syntax: &Syntax,
+    scanner: &Scanner,
+    todos: &mut Vec<TodoItem>,
+) {
+    let matcher = &scanner.matcher;
+    let lines: Vec<&str> = contents.lines().collect();
+    let line_index = LineIndex::new(contents);
+    let comments = lexer::comments(contents, syntax);
+
+    for (i, comment) in comments.iter().enumerate() {
+        let text_lines = comment_lines(comment, contents);
+
+        for (j, first) in text_lines.iter().enumerate() {
+            let Some((marker, severity)) = matcher.leading_marker(first.text) else {
+                continue;
+            };
+
+            let (rest, comment_end) = match comment.kind {
+                // The rest of a block comment belongs to the TODO, up to a blank line or another marker
+                CommentKind::Block => {
+                    let rest: Vec<&str> = text_lines[j + 1..]
+                        .iter()
+                        .map(|line| line.text)
+                        .take_while(|text| !text.is_empty() && !matcher.starts_with_marker(text))
+                        .collect();
+                    (rest, comment.span.end)
+                }
+                CommentKind::Line => {
+                    let following = &comments[i + 1..];
+                    let rest =
+                        continuation_lines(comment, first.indent, following, contents, matcher);
+                    let last = rest.len().checked_sub(1).map_or(comment, |k| &following[k]);
+                    (rest, last.span.end)
+                }
+            };
+
+            let (_, parsed) = parse_todo(first.text, scanner).unwrap();
+            let message: Vec<&str> = std::iter::once(parsed.message)
+                .chain(rest.iter().copied())
+                .skip_while(|text| text.is_empty())
+                .collect();
+            let body: Vec<&str> = std::iter::once(first.text).chain(rest).collect();
+            let message = message.join("\n");
+
+            // Issues linked anywhere in the text are tracked too
+            let mut references = parsed.references;
+            for reference in parser::url_references(&message) {
+                if !references.contains(&reference) {
+                    references.push(reference);
+                }
             }
 
-            return None;
+            let line = lines[first.line_number - 1];
+            todos.push(TodoItem {
+                file_path: file_path.to_path_buf(),
+                line_number: first.line_number,
+                end_line_number: first.line_number + body.len() - 1,
+                line_content: line.trim().to_string(),
+                body: body.join("\n"),
+                marker: marker.to_string(),
+                severity,
+                message,
+                text_span: line_index.span(first.offset..first.offset + first.text.len()),
+                comment_span: line_index.span(comment.span.start..comment_end),
+                reference_span: parsed.reference_span.map(|range| {
+                    line_index.span(first.offset + range.start..first.offset + range.end)
+                }),
+                references,
+                owner: parsed.owner,
+                attributes: parsed.attributes,
+                diagnostics: parsed
+                    .diagnostics
+                    .into_iter()
+                    .map(|(kind, range)| Diagnostic {
+                        kind,
+                        span: line_index.span(first.offset + range.start..first.offset + range.end),
+                    })
+                    .collect(),
+            });
+        }
+    }
+}
+
+/// A line of comment text, with decoration like the ` * ` in block comments or the repeated
+/// delimiters in `///`, `;;` or `%%` removed.
+struct CommentLine<'a> {
+    line_number: usize,
+    text: &'a str,
+    /// Byte offset of the text in the file
+    offset: usize,
+    /// How many bytes of whitespace and decoration came before the text
+    indent: usize,
+}
+
+fn comment_lines<'a>(comment: &Comment, contents: &'a str) -> Vec<CommentLine<'a>> {
+    let mut line_start = comment.body.start;
+    comment
+        .lines(contents)
+        .map(|(line_number, raw)| {
+            let text = raw.trim_start_matches(|c: char| {
+                c.is_whitespace() || matches!(c, '*' | '/' | '!' | '#' | ';' | '%' | '-' | '\'')
+            });
+            let indent = raw.len() - text.len();
+            let line = CommentLine {
+                line_number,
+                text: text.trim_end(),
+                offset: line_start + indent,
+                indent,
+            };
+            line_start += raw.len() + 1;
+            line
+        })
+        .collect()
+}
+
+/// Line comments right below a TODO line comment that continue its text. They have to be
+/// indented past the TODO's own text, so a regular comment that follows isn't swallowed:
+///
+/// ```text
+/// // TODO(TASK-1): first line
+/// //   second line
+/// ```
+fn continuation_lines<'a>(
+    todo: &Comment,
+    todo_indent: usize,
+    following: &[Comment],
+    contents: &'a str,
+    matcher: &TodoMatcher,
+) -> Vec<&'a str> {
+    let column = |pos: usize| pos - contents[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let todo_column = column(todo.span.start);
+    let mut continued = Vec::new();
+
+    for (next_line, comment) in (todo.start_line + 1..).zip(following) {
+        let start = comment.span.start;
+        let alone_on_line = contents[start - column(start)..start].trim().is_empty();
+        if comment.kind != CommentKind::Line
+            || comment.start_line != next_line
+            || column(start) != todo_column
+            || !alone_on_line
+        {
+            break;
         }
+
+        let line = &comment_lines(comment, contents)[0];
+        if line.indent <= todo_indent
+            || line.text.is_empty()
+            || matcher.starts_with_marker(line.text)
+        {
+            break;
+        }
+        continued.push(line.text);
     }
 
-    None
+    continued
 }
 
 #[cfg(test)]
-mod parse_todo_attributes_tests {
+mod find_todos_in_content_tests {
     use super::*;
 
+    fn find(contents: &str, file_name: &str) -> Vec<TodoItem> {
+        let path = Path::new(file_name);
+        let mut todos = Vec::new();
+        let scanner = Scanner::default();
+        let syntax = scanner.languages.syntax_for(path);
+        find_todos_in_content(contents, path, syntax, &scanner, &mut todos);
+        todos
+    }
+
+    #[test]
+    fn single_line() {
+        let todos = find("let x = 1; // TODO: one line\n", "a.rs");
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].line_number, 1);
+        assert_eq!(todos[0].end_line_number, 1);
+        assert_eq!(todos[0].body, "TODO: one line");
+    }
+
+    #[test]
+    fn block_comment_spanning_lines() {
+        let contents = "/*\n * TODO(TASK-1): first\n * second\n *\n * unrelated\n */\n";
+        let todos = find(contents, "a.c");
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].line_number, 2);
+        assert_eq!(todos[0].end_line_number, 3);
+        assert_eq!(todos[0].body, "TODO(TASK-1): first\nsecond");
+    }
+
+    #[test]
+    fn block_comment_with_two_markers() {
+        let contents = "/* TODO: first\n   FIXME: second\n   more */";
+        let todos = find(contents, "a.c");
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[0].body, "TODO: first");
+        assert_eq!(todos[1].body, "FIXME: second\nmore");
+        assert_eq!(todos[1].end_line_number, 3);
+    }
+
+    #[test]
+    fn python_docstring() {
+        let contents = "def f():\n    \"\"\"Does things.\n\n    TODO: handle errors\n    and retries\n    \"\"\"\n";
+        let todos = find(contents, "a.py");
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].line_number, 4);
+        assert_eq!(todos[0].end_line_number, 5);
+        assert_eq!(todos[0].body, "TODO: handle errors\nand retries");
+    }
+
+    #[test]
+    fn html_comment_block() {
+        let contents = "<!--\n  TODO: first\n  second\n-->\n";
+        let todos = find(contents, "a.html");
+        assert_eq!(todos[0].body, "TODO: first\nsecond");
+    }
+
+    #[test]
+    fn indented_line_comment_continuation() {
+        let contents =
+            "    // TODO: first\n    //   second\n    //   third\n    // not part of it\n";
+        let todos = find(contents, "a.rs");
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].end_line_number, 3);
+        assert_eq!(todos[0].body, "TODO: first\nsecond\nthird");
+    }
+
+    #[test]
+    fn unindented_line_comment_is_not_continuation() {
+        let contents = "// TODO: first\n// Regular comment about the next function\n";
+        let todos = find(contents, "a.rs");
+        assert_eq!(todos[0].end_line_number, 1);
+        assert_eq!(todos[0].body, "TODO: first");
+    }
+
+    #[test]
+    fn consecutive_todos_stay_separate() {
+        let contents = "# TODO: first\n#   TODO: second\n";
+        let todos = find(contents, "a.py");
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[0].body, "TODO: first");
+    }
+
     #[test]
-    fn some_attributes() {
-        let line = "// TODO(TASK-123, bidir, labels=urgent,backend, assignee=alice): Fix bug";
-        let attributes = parse_todo_attributes(line).unwrap();
+    fn message_and_spans() {
+        let contents = "fn main() {\n    let x = 1; // TODO(TASK-1, p=high): caf\u{e9} later\n}\n";
+        let todo = &find(contents, "a.rs")[0];
+        assert_eq!(todo.message, "caf\u{e9} later");
 
+        let comment = todo.comment_span;
         assert_eq!(
-            attributes.get("bidir"),
-            Some(&TodoAttributeValue::Flag(true))
+            &contents[comment.start..comment.end],
+            "// TODO(TASK-1, p=high): caf\u{e9} later"
         );
+        assert_eq!((comment.start_line, comment.start_column), (2, 16));
+        assert_eq!((comment.end_line, comment.end_column), (2, 51));
+
+        let reference = todo.reference_span.unwrap();
         assert_eq!(
-            attributes.get("labels"),
-            Some(&TodoAttributeValue::List(vec![
-                "urgent".to_string(),
-                "backend".to_string()
-            ]))
+            &contents[reference.start..reference.end],
+            "(TASK-1, p=high)"
         );
+        assert_eq!((reference.start_column, reference.end_column), (23, 39));
+    }
+
+    #[test]
+    fn multi_line_message_and_spans() {
+        let contents = "/*\n * TODO:\n *   first\n *   second\n */\nx\n";
+        let todo = &find(contents, "a.c")[0];
+        assert_eq!(todo.message, "first\nsecond");
+        assert_eq!(todo.reference_span, None);
+        assert_eq!(todo.comment_span.start_line, 1);
+        assert_eq!(todo.comment_span.end_line, 5);
+
+        let contents = "// TODO: first\n//   second\n// other\n";
+        let todo = &find(contents, "a.rs")[0];
+        assert_eq!(todo.message, "first\nsecond");
+        let span = todo.comment_span;
+        assert_eq!(
+            &contents[span.start..span.end],
+            "// TODO: first\n//   second"
+        );
+    }
+
+    #[test]
+    fn urls_anywhere_in_the_message() {
+        let contents = "# TODO(TASK-1): flaky, see\n#   https://github.com/org/repo/issues/42\n";
+        let todo = &find(contents, "a.py")[0];
         assert_eq!(
-            attributes.get("assignee"),
-            Some(&TodoAttributeValue::Text("alice".to_string()))
+            todo.references,
+            vec![
+                TodoReference::Tracked("TASK-1".to_string()),
+                TodoReference::GitHub {
+                    repo: "org/repo".to_string(),
+                    number: 42
+                },
+            ]
         );
     }
 }
 
-fn parse_attributes_from_string(attributes_str: &str) -> HashMap<String, TodoAttributeValue> {
-    // To create a HashMap, we need to import it from std::collections
-    // and call HashMap::new()! Very similar to Java, C++, etc.
-    let mut attributes = HashMap::new();
+/// Parses the TODO in a line of comment text, anchored at the first marker in it.
+/// Returns where the marker starts, and the parse, whose ranges are relative to the marker.
+fn parse_todo<'a>(text: &'a str, scanner: &Scanner) -> Option<(usize, ParsedTodo<'a>)> {
+    let found = scanner.matcher.marker.find(text)?;
+    let parsed = parser::parse(
+        &text[found.start()..],
+        found.len(),
+        &scanner.ids,
+        &scanner.schema,
+    );
+    Some((found.start(), parsed))
+}
 
-    for attr in attributes_str.split(", ") {
-        let attr = attr.trim();
-        if attr.is_empty() {
-            continue;
-        }
+#[cfg(test)]
+mod parse_todo_tests {
+    use super::*;
 
-        if attr.contains('=') {
-            // `collect` here turns an iterator into a collection (Vec in this case)
-            let parts: Vec<&str> = attr.splitn(2, '=').collect();
-            let key = parts[0].trim().to_string();
-            let value = parts[1].trim();
-
-            if value.contains(',') {
-                // List attribute
-                let list_values: Vec<String> = value
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect();
-                attributes.insert(key, TodoAttributeValue::List(list_values));
-            } else {
-                // Text attribute
-                attributes.insert(key, TodoAttributeValue::Text(value.to_string()));
-            }
-        } else {
-            // Flag attribute
-            attributes.insert(attr.to_string(), TodoAttributeValue::Flag(true));
-        }
+    fn references(line: &str, matcher: TodoMatcher) -> Option<Vec<TodoReference>> {
+        let scanner = Scanner {
+            matcher,
+            ..Scanner::default()
+        };
+        parse_todo(line, &scanner).map(|(_, parsed)| parsed.references)
+    }
+
+    #[test]
+    fn test_parse_lowercase_case_insensitive() {
+        let line = "// todo(TASK-1): lowercase marker";
+        let result = references(line, TodoMatcher::new(true, &default_markers()));
+        assert_eq!(
+            result,
+            Some(vec![TodoReference::Tracked("TASK-1".to_string())])
+        );
     }
 
-    attributes
+    #[test]
+    fn test_parse_lowercase_case_sensitive() {
+        let line = "// todo(TASK-1): lowercase marker";
+        let result = references(line, TodoMatcher::new(false, &default_markers()));
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn parens_before_the_marker_are_not_the_reference() {
+        let line = "foo(x); // TODO: bar";
+        let (start, parsed) = parse_todo(line, &Scanner::default()).unwrap();
+        assert_eq!(start, 11);
+        assert_eq!(parsed.references, vec![]);
+        assert_eq!(parsed.reference_span, None);
+        assert_eq!(parsed.message, "bar");
+
+        let line = "let (a, b) = (TASK-1, 2); // TODO(TASK-2): baz";
+        let (_, parsed) = parse_todo(line, &Scanner::default()).unwrap();
+        assert_eq!(
+            parsed.references,
+            vec![TodoReference::Tracked("TASK-2".to_string())]
+        );
+    }
 }
 
 fn process_results(todos: &[TodoItem]) {
-    println!("Scanned and found {} TODOs", todos.len());
+    outln!("Scanned and found {} TODOs", todos.len());
 }
 
-fn print_results(todos: &[TodoItem], verbose: bool) {
+fn print_results(todos: &[TodoItem], verbose: bool, group_by: GroupBy) {
     if todos.is_empty() {
-        println!("No TODOs found.");
+        outln!("No TODOs found.");
         return;
     }
 
-    println!("\nFound {} TODOs:", todos.len());
+    outln!("\nFound {} TODOs:", todos.len());
 
     let mut todos_by_file: HashMap<&PathBuf, Vec<&TodoItem>> = HashMap::new();
 
@@ -471,21 +1691,77 @@ fn print_results(todos: &[TodoItem], verbose: bool) {
         todos_by_file.entry(&todo.file_path).or_default().push(todo);
     }
 
-    let mut sorted_files: Vec<_> = todos_by_file.keys().collect();
-    sorted_files.sort();
+    let groups: Vec<(String, Vec<&TodoItem>)> = match group_by {
+        GroupBy::File => {
+            let mut sorted_files: Vec<_> = todos_by_file.keys().collect();
+            sorted_files.sort();
+            sorted_files
+                .into_iter()
+                .map(|file_path| {
+                    let heading = format!("📁 {}:", file_path.display());
+                    (heading, todos_by_file[file_path].clone())
+                })
+                .collect()
+        }
+        GroupBy::Owner => {
+            let mut todos_by_owner: BTreeMap<&str, Vec<&TodoItem>> = BTreeMap::new();
+            let mut unowned = Vec::new();
+            for todo in todos {
+                match &todo.owner {
+                    Some(owner) => todos_by_owner.entry(owner).or_default().push(todo),
+                    None => unowned.push(todo),
+                }
+            }
+            let mut groups: Vec<_> = todos_by_owner
+                .into_iter()
+                .map(|(owner, owned)| (format!("👤 {}:", owner), owned))
+                .collect();
+            if !unowned.is_empty() {
+                groups.push(("👤 (no owner):".to_string(), unowned));
+            }
+            groups
+        }
+    };
+
+    for (heading, group_todos) in groups {
+        outln!("{}", heading);
 
-    for file_path in sorted_files {
-        let file_todos = &todos_by_file[file_path];
-        println!("📁 {}:", file_path.display());
+        for todo in group_todos {
+            let mut lines = if todo.end_line_number > todo.line_number {
+                format!("Lines {}-{}", todo.line_number, todo.end_line_number)
+            } else {
+                format!("Line {}", todo.line_number)
+            };
+            if group_by == GroupBy::Owner {
+                lines = format!("{}, {}", todo.file_path.display(), lines);
+            }
 
-        for todo in file_todos {
             if verbose {
-                println!("  Line {}: {}", todo.line_number, todo.line_content);
-                match &todo.reference {
-                    Some(TodoReference::Untracked) => println!("    Not synced"),
-                    Some(TodoReference::Tracked(id)) => println!("    Tracking issue {}", id),
-                    Some(TodoReference::New { title }) => println!("    Will create: {:?}", title),
-                    None => println!("    No reference found"),
+                outln!("  {}: {}", lines, todo.line_content);
+                for continued in todo.body.lines().skip(1) {
+                    outln!("    | {}", continued);
+                }
+                outln!("    Marker: {} ({})", todo.marker, todo.severity);
+                if let Some(owner) = &todo.owner {
+                    outln!("    Owner: {}", owner);
+                }
+                if todo.references.is_empty() {
+                    outln!("    Not synced");
+                }
+                for reference in &todo.references {
+                    match reference {
+                        TodoReference::Untracked => outln!("    Not synced"),
+                        TodoReference::Tracked(id) => outln!("    Tracking issue {}", id),
+                        TodoReference::GitHub { .. }
+                        | TodoReference::GitLab { .. }
+                        | TodoReference::Jira { .. }
+                        | TodoReference::Notion { .. } => outln!(
+                            "    Tracking {} issue {}",
+                            reference.provider().unwrap(),
+                            reference.issue().unwrap()
+                        ),
+                        TodoReference::New { title } => outln!("    Will create: {:?}", title),
+                    }
                 }
                 match &todo.attributes {
                     Some(attrs) => {
@@ -493,26 +1769,54 @@ fn print_results(todos: &[TodoItem], verbose: bool) {
                         keys.sort();
                         for key in keys {
                             match &attrs[key] {
-                                TodoAttributeValue::Flag(b) => println!("    - {}: {}", key, b),
-                                TodoAttributeValue::Text(s) => println!("    - {}: {}", key, s),
-                                TodoAttributeValue::List(vs) => println!("    - {}: {:?}", key, vs),
+                                TodoAttributeValue::Flag(b) => outln!("    - {}: {}", key, b),
+                                TodoAttributeValue::Text(s) => outln!("    - {}: {}", key, s),
+                                TodoAttributeValue::List(vs) => outln!("    - {}: {:?}", key, vs),
+                                TodoAttributeValue::Date(d) => outln!("    - {}: {}", key, d),
+                                TodoAttributeValue::Number(n) => outln!("    - {}: {}", key, n),
                             }
                         }
                     }
-                    None => println!("    No attributes"),
+                    None => outln!("    No attributes"),
                 }
             } else {
-                println!("  Line {}", todo.line_number);
+                outln!("  {}", lines);
             }
         }
-        println!();
-        println!("==========================");
-        println!();
+        outln!();
+        outln!("==========================");
+        outln!();
     }
 
-    println!(
+    outln!(
         "Total: {} TODOs across {} file(s)",
         todos.len(),
         todos_by_file.len()
     );
 }
+
+fn print_diagnostics(todos: &[TodoItem]) {
+    let malformed: Vec<_> = todos
+        .iter()
+        .flat_map(|todo| {
+            todo.diagnostics
+                .iter()
+                .map(move |diagnostic| (todo, diagnostic))
+        })
+        .collect();
+    if malformed.is_empty() {
+        return;
+    }
+
+    outln!("\nERROR: {} malformed TODO(s)", malformed.len());
+    for (todo, diagnostic) in malformed {
+        outln!(
+            "{}:{}:{}: {}",
+            todo.file_path.display(),
+            diagnostic.span.start_line,
+            diagnostic.span.start_column,
+            diagnostic.kind
+        );
+        outln!("    {}", todo.line_content);
+    }
+}
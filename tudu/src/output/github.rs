@@ -0,0 +1,223 @@
+//! `--format github`: output for GitHub Actions. Malformed TODOs become `::error` workflow
+//! commands, and references to issues that don't exist `::warning`s, which GitHub shows inline
+//! on pull request diffs. Files that couldn't be scanned, and issue providers that couldn't be
+//! asked, are `::warning`s too.
+//!
+//! The counts, and every failure, also go into a Markdown summary for the job's page; see
+//! [`summary`].
+
+use std::path::Path;
+
+use crate::error::TuduError;
+use crate::lexer::Span;
+use crate::{Severity, TodoItem, TodoReference};
+
+/// The workflow commands for a scan, one per line.
+pub fn annotations(
+    todos: &[TodoItem],
+    unknown: &[(&TodoItem, &TodoReference)],
+    warnings: &[TuduError],
+) -> Vec<String> {
+    let mut commands = Vec::new();
+    for todo in todos {
+        for diagnostic in &todo.diagnostics {
+            commands.push(command(
+                "error",
+                Some(&todo.file_path),
+                Some(&diagnostic.span),
+                &format!("Malformed TODO ({})", diagnostic.kind.code()),
+                &diagnostic.kind.to_string(),
+            ));
+        }
+    }
+    for (todo, reference) in unknown {
+        commands.push(command(
+            "warning",
+            Some(&todo.file_path),
+            Some(todo.reference_span.as_ref().unwrap_or(&todo.text_span)),
+            "Unknown issue",
+            &unknown_issue(reference),
+        ));
+    }
+    for warning in warnings {
+        let path = warning.path().map(|path| path.as_path());
+        commands.push(command(
+            "warning",
+            path,
+            None,
+            if path.is_some() {
+                "Skipped file"
+            } else {
+                "Issues not checked"
+            },
+            &warning.to_string(),
+        ));
+    }
+    commands
+}
+
+/// The job summary: a table of how many TODOs there are at each severity, then a table of
+/// every failure, if there are any.
+pub fn summary(todos: &[TodoItem], unknown: &[(&TodoItem, &TodoReference)]) -> String {
+    let mut markdown = String::from("## tudu\n\n| Severity | TODOs |\n| --- | ---: |\n");
+    for severity in [Severity::Error, Severity::Warning, Severity::Info] {
+        let count = todos
+            .iter()
+            .filter(|todo| todo.severity == severity)
+            .count();
+        markdown.push_str(&format!("| {} | {} |\n", severity, count));
+    }
+    markdown.push_str(&format!("| **Total** | **{}** |\n", todos.len()));
+
+    let malformed = todos
+        .iter()
+        .filter(|todo| !todo.diagnostics.is_empty())
+        .count();
+    let untracked = todos
+        .iter()
+        .filter(|todo| todo.references.is_empty())
+        .count();
+    markdown.push_str(&format!(
+        "\n{} untracked, {} malformed, {} referencing issues that don't exist\n",
+        untracked,
+        malformed,
+        unknown.len()
+    ));
+
+    let failures: Vec<_> = todos
+        .iter()
+        .flat_map(|todo| {
+            todo.diagnostics
+                .iter()
+                .map(move |diagnostic| (todo, diagnostic.kind.to_string()))
+        })
+        .chain(
+            unknown
+                .iter()
+                .map(|(todo, reference)| (*todo, unknown_issue(reference))),
+        )
+        .collect();
+    if !failures.is_empty() {
+        markdown.push_str("\n### Failures\n\n| File | Line | Problem |\n| --- | ---: | --- |\n");
+        for (todo, problem) in failures {
+            markdown.push_str(&format!(
+                "| `{}` | {} | {} |\n",
+                cell(&todo.file_path.display().to_string()),
+                todo.line_number,
+                cell(&problem)
+            ));
+        }
+    }
+    markdown
+}
+
+fn unknown_issue(reference: &TodoReference) -> String {
+    format!("{} doesn't exist", reference.issue().unwrap_or_default())
+}
+
+/// A workflow command like `::error file=src/lib.rs,line=3,col=9,title=...::message`.
+fn command(
+    level: &str,
+    file: Option<&Path>,
+    span: Option<&Span>,
+    title: &str,
+    message: &str,
+) -> String {
+    let mut properties = Vec::new();
+    if let Some(file) = file {
+        properties.push(format!("file={}", property(&file.display().to_string())));
+    }
+    if let Some(span) = span {
+        properties.push(format!("line={}", span.start_line));
+        properties.push(format!("endLine={}", span.end_line));
+        // Columns only mean something on a single line
+        if span.start_line == span.end_line {
+            properties.push(format!("col={}", span.start_column));
+            properties.push(format!("endColumn={}", span.end_column));
+        }
+    }
+    properties.push(format!("title={}", property(title)));
+    format!("::{} {}::{}", level, properties.join(","), data(message))
+}
+
+/// Escapes a command's message, which ends at the end of the line.
+fn data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a command's property, which also ends at a `,` or `:`.
+fn property(text: &str) -> String {
+    data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Escapes a Markdown table cell, which ends at a `|` or the end of the line.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::output::tests::sample;
+
+    #[test]
+    fn commands() {
+        let todo = sample();
+        let reference = TodoReference::Tracked("TASK-1".to_string());
+        let warning = TuduError::UnknownEncoding {
+            path: PathBuf::from("latin1.py"),
+        };
+        let commands = annotations(
+            std::slice::from_ref(&todo),
+            &[(&todo, &reference)],
+            &[warning],
+        );
+        assert_eq!(
+            commands,
+            [
+                "::error file=src/lib.rs,line=1,endLine=1,col=8,endColumn=42,title=Malformed TODO (missing-attribute)::missing required attribute 'due'",
+                "::warning file=src/lib.rs,line=1,endLine=1,col=8,endColumn=42,title=Unknown issue::TASK-1 doesn't exist",
+                "::warning file=latin1.py,title=Skipped file::'latin1.py' is not UTF-8 or any encoding in scan.encodings",
+            ]
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(data("100% done\r\nnext"), "100%25 done%0D%0Anext");
+        assert_eq!(property("a,b: c"), "a%2Cb%3A c");
+        assert_eq!(cell("a | b"), "a \\| b");
+    }
+
+    #[test]
+    fn summaries() {
+        let mut clean = sample();
+        clean.diagnostics.clear();
+        clean.severity = Severity::Error;
+        let markdown = summary(&[sample(), clean], &[]);
+        assert_eq!(
+            markdown,
+            "## tudu
+
+| Severity | TODOs |
+| --- | ---: |
+| error | 1 |
+| warning | 1 |
+| info | 0 |
+| **Total** | **2** |
+
+0 untracked, 1 malformed, 0 referencing issues that don't exist
+
+### Failures
+
+| File | Line | Problem |
+| --- | ---: | --- |
+| `src/lib.rs` | 1 | missing required attribute 'due' |
+"
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! `--format json`: one JSON document with every TODO found, what's malformed about each, the
+//! references to issues that don't exist, and the files that couldn't be scanned. Its shape is
+//! described by the JSON Schema in `schema/output.v1.schema.json`.
+//!
+//! The document's `version` goes up whenever a field is removed or changes meaning, so
+//! consumers can tell a document they can't read. Adding a field doesn't change it.
+
+use serde_json::{Map, Value, json};
+
+use crate::error::TuduError;
+use crate::lexer::Span;
+use crate::{Severity, TodoAttributeValue, TodoItem, TodoReference};
+
+/// The version of the document's shape, and of the schema that describes it.
+pub const VERSION: u64 = 1;
+
+/// The JSON Schema for the document.
+pub const SCHEMA: &str = include_str!("../../schema/output.v1.schema.json");
+
+/// The whole document for a scan. `unknown` are the references a provider said don't exist.
+pub fn report(
+    todos: &[TodoItem],
+    unknown: &[(&TodoItem, &TodoReference)],
+    warnings: &[TuduError],
+) -> Value {
+    json!({
+        "version": VERSION,
+        "summary": summary(todos, unknown, warnings),
+        "todos": todos.iter().map(todo).collect::<Vec<_>>(),
+        "unknown_issues": unknown_issues(unknown),
+        "warnings": warnings.iter().map(warning).collect::<Vec<_>>(),
+    })
+}
+
+/// Counts of what was found, so consumers don't have to walk every TODO for them.
+pub fn summary(
+    todos: &[TodoItem],
+    unknown: &[(&TodoItem, &TodoReference)],
+    warnings: &[TuduError],
+) -> Value {
+    let by_severity = |severity: Severity| {
+        todos
+            .iter()
+            .filter(|todo| todo.severity == severity)
+            .count()
+    };
+    json!({
+        "todos": todos.len(),
+        "malformed": todos.iter().filter(|todo| !todo.diagnostics.is_empty()).count(),
+        "untracked": todos.iter().filter(|todo| todo.references.is_empty()).count(),
+        "by_severity": {
+            "info": by_severity(Severity::Info),
+            "warning": by_severity(Severity::Warning),
+            "error": by_severity(Severity::Error),
+        },
+        "unknown_issues": unknown.len(),
+        "warnings": warnings.len(),
+    })
+}
+
+/// One TODO, with every field of its `TodoItem`.
+pub fn todo(todo: &TodoItem) -> Value {
+    json!({
+        "file": todo.file_path.display().to_string(),
+        "line": todo.line_number,
+        "end_line": todo.end_line_number,
+        "line_content": todo.line_content,
+        "body": todo.body,
+        "marker": todo.marker,
+        "severity": todo.severity.to_string(),
+        "message": todo.message,
+        "text_span": span(&todo.text_span),
+        "comment_span": span(&todo.comment_span),
+        "reference_span": todo.reference_span.as_ref().map(span),
+        "references": todo.references.iter().map(reference).collect::<Vec<_>>(),
+        "owner": todo.owner,
+        "attributes": todo.attributes.as_ref().map(|attributes| {
+            attributes
+                .iter()
+                .map(|(key, value)| (key.clone(), attribute(value)))
+                .collect::<Map<_, _>>()
+        }),
+        "valid": todo.diagnostics.is_empty(),
+        "diagnostics": todo.diagnostics.iter().map(|diagnostic| json!({
+            "code": diagnostic.kind.code(),
+            "message": diagnostic.kind.to_string(),
+            "span": span(&diagnostic.span),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+/// The references to issues that don't exist, each with where it's written.
+pub fn unknown_issues(unknown: &[(&TodoItem, &TodoReference)]) -> Value {
+    unknown
+        .iter()
+        .map(|(todo, issue)| {
+            json!({
+                "file": todo.file_path.display().to_string(),
+                "line": todo.line_number,
+                "span": span(todo.reference_span.as_ref().unwrap_or(&todo.text_span)),
+                "reference": reference(issue),
+            })
+        })
+        .collect()
+}
+
+fn span(span: &Span) -> Value {
+    json!({
+        "start": span.start,
+        "end": span.end,
+        "start_line": span.start_line,
+        "start_column": span.start_column,
+        "end_line": span.end_line,
+        "end_column": span.end_column,
+    })
+}
+
+/// A reference as `{"kind": ..., "issue": ...}`, plus the fields of its kind.
+fn reference(reference: &TodoReference) -> Value {
+    let mut value = match reference {
+        TodoReference::Untracked => json!({ "kind": "untracked" }),
+        TodoReference::Tracked(id) => json!({ "kind": "tracked", "id": id }),
+        TodoReference::GitHub { repo, number } => {
+            json!({ "kind": "github", "repo": repo, "number": number })
+        }
+        TodoReference::GitLab { project, number } => {
+            json!({ "kind": "gitlab", "project": project, "number": number })
+        }
+        TodoReference::Jira { key } => json!({ "kind": "jira", "key": key }),
+        TodoReference::Notion { page_id } => json!({ "kind": "notion", "page_id": page_id }),
+        TodoReference::New { title } => json!({ "kind": "new", "title": title }),
+    };
+    value["issue"] = json!(reference.issue());
+    value
+}
+
+/// Flags are booleans, lists arrays, numbers numbers, and dates `YYYY-MM-DD` strings.
+fn attribute(value: &TodoAttributeValue) -> Value {
+    match value {
+        TodoAttributeValue::Flag(flag) => json!(flag),
+        TodoAttributeValue::Text(text) => json!(text),
+        TodoAttributeValue::List(values) => json!(values),
+        TodoAttributeValue::Date(date) => json!(date.to_string()),
+        TodoAttributeValue::Number(number) => json!(number),
+    }
+}
+
+/// A file that couldn't be scanned, or a provider that couldn't be asked about references.
+pub fn warning(warning: &TuduError) -> Value {
+    json!({
+        "file": warning.path().map(|path| path.display().to_string()),
+        "message": warning.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::output::tests::sample;
+
+    fn keys(value: &Value) -> BTreeSet<&str> {
+        value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn required<'a>(schema: &'a Value, pointer: &str) -> BTreeSet<&'a str> {
+        schema
+            .pointer(pointer)
+            .and_then(Value::as_array)
+            .unwrap()
+            .iter()
+            .map(|key| key.as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn todos() {
+        let value = todo(&sample());
+        assert_eq!(value["file"], "src/lib.rs");
+        assert_eq!(value["severity"], "warning");
+        assert_eq!(value["owner"], "alice");
+        assert_eq!(value["valid"], false);
+        assert_eq!(value["text_span"]["start_column"], 4);
+        assert_eq!(
+            value["references"],
+            json!([
+                { "kind": "tracked", "id": "TASK-1", "issue": "TASK-1" },
+                { "kind": "github", "repo": "org/app", "number": 12, "issue": "gh:org/app#12" },
+            ])
+        );
+        assert_eq!(
+            value["attributes"],
+            json!({ "bidir": true, "labels": ["a", "b"] })
+        );
+        assert_eq!(
+            value["diagnostics"][0]["message"],
+            "missing required attribute 'due'"
+        );
+        assert_eq!(value["diagnostics"][0]["code"], "missing-attribute");
+    }
+
+    #[test]
+    fn summaries() {
+        let mut tracked = sample();
+        tracked.diagnostics.clear();
+        let mut untracked = sample();
+        untracked.references.clear();
+        untracked.severity = Severity::Error;
+
+        let warnings = [TuduError::UnknownEncoding {
+            path: PathBuf::from("latin1.py"),
+        }];
+        let issue = TodoReference::Tracked("TASK-1".to_string());
+        let value = report(
+            &[tracked.clone(), untracked],
+            &[(&tracked, &issue)],
+            &warnings,
+        );
+        assert_eq!(value["version"], VERSION);
+        assert_eq!(
+            value["summary"],
+            json!({
+                "todos": 2,
+                "malformed": 1,
+                "untracked": 1,
+                "by_severity": { "info": 0, "warning": 1, "error": 1 },
+                "unknown_issues": 1,
+                "warnings": 1,
+            })
+        );
+        assert_eq!(value["unknown_issues"][0]["line"], 1);
+        assert_eq!(value["unknown_issues"][0]["reference"]["issue"], "TASK-1");
+        assert_eq!(value["warnings"][0]["file"], "latin1.py");
+    }
+
+    #[test]
+    fn schema_matches_the_output() {
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        assert_eq!(schema["properties"]["version"]["const"], VERSION);
+
+        let todo = sample();
+        let issue = TodoReference::Tracked("TASK-1".to_string());
+        let value = report(std::slice::from_ref(&todo), &[(&todo, &issue)], &[]);
+        assert_eq!(keys(&value), required(&schema, "/required"));
+        assert_eq!(
+            keys(&value["summary"]),
+            required(&schema, "/$defs/summary/required")
+        );
+        assert_eq!(
+            keys(&value["todos"][0]),
+            required(&schema, "/$defs/todo/required")
+        );
+        assert_eq!(
+            keys(&value["unknown_issues"][0]),
+            required(&schema, "/$defs/unknown_issue/required")
+        );
+        assert_eq!(
+            keys(&value["todos"][0]["text_span"]),
+            required(&schema, "/$defs/span/required")
+        );
+
+        let codes: Vec<_> = schema["$defs"]["diagnostic"]["properties"]["code"]["enum"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|code| code.as_str().unwrap())
+            .collect();
+        assert!(
+            codes.contains(
+                &value["todos"][0]["diagnostics"][0]["code"]
+                    .as_str()
+                    .unwrap()
+            )
+        );
+    }
+}
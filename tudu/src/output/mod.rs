@@ -0,0 +1,55 @@
+//! Machine-readable reports of a scan, for dashboards and CI. The human-readable output is
+//! printed by the binary itself.
+
+pub mod github;
+pub mod json;
+pub mod ndjson;
+pub mod sarif;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use crate::lexer::LineIndex;
+    use crate::parser::{Diagnostic, DiagnosticKind};
+    use crate::{Severity, TodoAttributeValue, TodoItem, TodoReference};
+
+    /// A tracked TODO with an owner, attributes and a diagnostic, for the formats to print.
+    pub fn sample() -> TodoItem {
+        let text = "// TODO(alice, TASK-1, bidir, labels=a,b): fix this";
+        let index = LineIndex::new(text);
+        TodoItem {
+            file_path: PathBuf::from("src/lib.rs"),
+            line_number: 1,
+            end_line_number: 1,
+            line_content: text.to_string(),
+            body: text[3..].to_string(),
+            marker: "TODO".to_string(),
+            severity: Severity::Warning,
+            message: "fix this".to_string(),
+            text_span: index.span(3..text.len()),
+            comment_span: index.span(0..text.len()),
+            reference_span: Some(index.span(7..41)),
+            references: vec![
+                TodoReference::Tracked("TASK-1".to_string()),
+                TodoReference::GitHub {
+                    repo: "org/app".to_string(),
+                    number: 12,
+                },
+            ],
+            owner: Some("alice".to_string()),
+            attributes: Some(HashMap::from([
+                ("bidir".to_string(), TodoAttributeValue::Flag(true)),
+                (
+                    "labels".to_string(),
+                    TodoAttributeValue::List(vec!["a".to_string(), "b".to_string()]),
+                ),
+            ])),
+            diagnostics: vec![Diagnostic {
+                kind: DiagnosticKind::MissingAttribute("due".to_string()),
+                span: index.span(7..41),
+            }],
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! `--format ndjson`: one JSON record per line, written as files are scanned, so consumers like
+//! `jq` can start on them before the scan is done.
+//!
+//! Every TODO is a `{"type": "todo", ...}` record with the fields of a TODO in the JSON
+//! output. They come in the order files are scanned, which isn't stable with more than one
+//! thread. A `{"type": "summary", ...}` record, with the JSON output's summary, unknown issues
+//! and warnings, always comes last. Every record has the JSON output's `version`.
+
+use serde_json::{Value, json};
+
+use crate::error::TuduError;
+use crate::output::json;
+use crate::{TodoItem, TodoReference};
+
+/// A TODO's record, as one line.
+pub fn todo(todo: &TodoItem) -> String {
+    let mut record = json::todo(todo);
+    record["type"] = json!("todo");
+    record["version"] = json!(json::VERSION);
+    record.to_string()
+}
+
+/// The last record, once the scan is done.
+pub fn summary(
+    todos: &[TodoItem],
+    unknown: &[(&TodoItem, &TodoReference)],
+    warnings: &[TuduError],
+) -> String {
+    let record: Value = json!({
+        "type": "summary",
+        "version": json::VERSION,
+        "summary": json::summary(todos, unknown, warnings),
+        "unknown_issues": json::unknown_issues(unknown),
+        "warnings": warnings.iter().map(json::warning).collect::<Vec<_>>(),
+    });
+    record.to_string()
+}
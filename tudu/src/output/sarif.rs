@@ -0,0 +1,357 @@
+//! `--format sarif`: a SARIF 2.1.0 log, for uploading next to other static analysis results.
+//!
+//! Every TODO is a result of the `todo` rule, at the level of its marker's severity. Each
+//! thing that's malformed about a TODO is a result of the rule named by its diagnostic's code,
+//! and each reference to an issue its tracker doesn't have is an `unknown-issue`; those are
+//! all errors. References to issues that have been closed are `closed-issue` warnings, since
+//! the TODO is probably done, or needs a new issue.
+//!
+//! Results have `partialFingerprints` built from the rule, the file and the TODO's text, but
+//! not the line it's on, so code scanning can follow a finding as the lines around it change.
+
+use std::collections::HashMap;
+use std::path::{Component, Path};
+
+use serde_json::{Value, json};
+
+use crate::error::TuduError;
+use crate::lexer::Span;
+use crate::{Severity, TodoItem, TodoReference};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// The key of the fingerprints in `partialFingerprints`. Changing how they're computed needs a
+/// new version, or every finding would look new.
+const FINGERPRINT: &str = "tudu/v1";
+
+/// Every rule, and what it finds. Results point into this by index.
+const RULES: &[(&str, &str)] = &[
+    ("todo", "A TODO comment"),
+    (
+        "unterminated-parens",
+        "A `(` after the marker with no `)` to close it",
+    ),
+    (
+        "unterminated-quote",
+        "A `\"` inside the parentheses with no `\"` to close it",
+    ),
+    ("empty-id", "Nothing where the TODO's reference should be"),
+    (
+        "bad-reference",
+        "A qualified reference that doesn't fit its tracker",
+    ),
+    (
+        "bad-attribute",
+        "An attribute of the wrong type, or that couldn't be read",
+    ),
+    ("unknown-attribute", "An attribute that isn't in the schema"),
+    (
+        "missing-attribute",
+        "An attribute the schema requires, which the TODO doesn't set",
+    ),
+    (
+        "unknown-issue",
+        "A reference to an issue its tracker doesn't have",
+    ),
+    ("closed-issue", "A reference to an issue that's been closed"),
+];
+
+/// The log for a scan. `unknown` are the references their provider says don't exist, and
+/// `closed` the ones it says are closed.
+pub fn report(
+    todos: &[TodoItem],
+    unknown: &[(&TodoItem, &TodoReference)],
+    closed: &[(&TodoItem, &TodoReference)],
+    warnings: &[TuduError],
+) -> Value {
+    let mut results = Results::default();
+    for todo in todos {
+        results.push(
+            "todo",
+            level(todo.severity),
+            &todo.body,
+            todo,
+            &todo.text_span,
+            "",
+        );
+        for diagnostic in &todo.diagnostics {
+            let message = diagnostic.kind.to_string();
+            results.push(
+                diagnostic.kind.code(),
+                "error",
+                &message,
+                todo,
+                &diagnostic.span,
+                &message,
+            );
+        }
+    }
+    for (todo, reference) in unknown {
+        let issue = reference.issue().unwrap_or_default();
+        results.push(
+            "unknown-issue",
+            "error",
+            &format!("{} doesn't exist", issue),
+            todo,
+            todo.reference_span.as_ref().unwrap_or(&todo.text_span),
+            &issue,
+        );
+    }
+    for (todo, reference) in closed {
+        let issue = reference.issue().unwrap_or_default();
+        results.push(
+            "closed-issue",
+            "warning",
+            &format!("{} is closed", issue),
+            todo,
+            todo.reference_span.as_ref().unwrap_or(&todo.text_span),
+            &issue,
+        );
+    }
+
+    let rules: Vec<_> = RULES
+        .iter()
+        .map(|(id, description)| {
+            json!({
+                "id": id,
+                "shortDescription": { "text": description },
+            })
+        })
+        .collect();
+    let notifications: Vec<_> = warnings
+        .iter()
+        .map(|warning| {
+            json!({
+                "level": "warning",
+                "message": { "text": warning.to_string() },
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "tudu",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            // Columns count characters, not UTF-16 code units
+            "columnKind": "unicodeCodePoints",
+            "invocations": [{
+                "executionSuccessful": true,
+                "toolExecutionNotifications": notifications,
+            }],
+            "results": results.results,
+        }],
+    })
+}
+
+/// The results so far, and how many of each fingerprint there are, so that identical TODOs in
+/// the same file still get fingerprints of their own.
+#[derive(Default)]
+struct Results {
+    results: Vec<Value>,
+    seen: HashMap<u64, u64>,
+}
+
+impl Results {
+    /// Adds a result about `todo`. `detail` tells results of the same rule on the same TODO
+    /// apart in the fingerprint, like two of its diagnostics.
+    fn push(
+        &mut self,
+        rule: &str,
+        level: &str,
+        message: &str,
+        todo: &TodoItem,
+        span: &Span,
+        detail: &str,
+    ) {
+        let uri = uri(&todo.file_path);
+        // Indentation isn't part of the TODO, since re-indenting code shouldn't make it new
+        let text: Vec<&str> = todo.body.lines().map(str::trim).collect();
+        let key = fnv1a(&[rule, &uri, &text.join("\n"), detail].join("\0"));
+        let occurrence = self.seen.entry(key).or_default();
+        *occurrence += 1;
+        let fingerprint = format!("{:016x}:{}", key, occurrence);
+
+        let rule_index = RULES.iter().position(|(id, _)| *id == rule);
+        self.results.push(json!({
+            "ruleId": rule,
+            "ruleIndex": rule_index,
+            "level": level,
+            "message": { "text": message },
+            "locations": [{
+                "physicalLocation": {
+                    "artifactLocation": { "uri": uri },
+                    "region": {
+                        "startLine": span.start_line,
+                        "startColumn": span.start_column,
+                        "endLine": span.end_line,
+                        "endColumn": span.end_column,
+                    },
+                },
+            }],
+            "partialFingerprints": { FINGERPRINT: fingerprint },
+        }));
+    }
+}
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Info => "note",
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+    }
+}
+
+/// A relative URI for a relative path, and a `file:` URI for an absolute one.
+fn uri(path: &Path) -> String {
+    let segments: Vec<String> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(segment) => Some(encode(&segment.to_string_lossy())),
+            Component::ParentDir => Some("..".to_string()),
+            Component::Prefix(prefix) => Some(prefix.as_os_str().to_string_lossy().into_owned()),
+            Component::CurDir | Component::RootDir => None,
+        })
+        .collect();
+    if path.has_root() {
+        format!("file:///{}", segments.join("/"))
+    } else {
+        segments.join("/")
+    }
+}
+
+/// Percent-encodes everything in a path segment but the characters URIs allow there.
+fn encode(segment: &str) -> String {
+    let mut encoded = String::new();
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=@".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// 64-bit FNV-1a, which is tiny and the same on every platform and release.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tests::sample;
+
+    fn fingerprints(log: &Value) -> Vec<&str> {
+        log["runs"][0]["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| result["partialFingerprints"][FINGERPRINT].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn results() {
+        let todo = sample();
+        let reference = TodoReference::Tracked("TASK-1".to_string());
+        let closed = TodoReference::GitHub {
+            repo: "org/app".to_string(),
+            number: 12,
+        };
+        let log = report(
+            std::slice::from_ref(&todo),
+            &[(&todo, &reference)],
+            &[(&todo, &closed)],
+            &[],
+        );
+        let results = log["runs"][0]["results"].as_array().unwrap();
+
+        let rules: Vec<_> = results.iter().map(|result| &result["ruleId"]).collect();
+        assert_eq!(
+            rules,
+            ["todo", "missing-attribute", "unknown-issue", "closed-issue"]
+        );
+        assert_eq!(results[0]["level"], "warning");
+        assert_eq!(results[0]["ruleIndex"], 0);
+        assert_eq!(
+            results[0]["locations"][0]["physicalLocation"],
+            json!({
+                "artifactLocation": { "uri": "src/lib.rs" },
+                "region": { "startLine": 1, "startColumn": 4, "endLine": 1, "endColumn": 52 },
+            })
+        );
+        assert_eq!(results[1]["level"], "error");
+        assert_eq!(results[2]["message"]["text"], "TASK-1 doesn't exist");
+        assert_eq!(results[3]["level"], "warning");
+        assert_eq!(results[3]["message"]["text"], "gh:org/app#12 is closed");
+        for result in results {
+            let id = result["ruleId"].as_str().unwrap();
+            assert_eq!(
+                log["runs"][0]["tool"]["driver"]["rules"]
+                    [result["ruleIndex"].as_u64().unwrap() as usize]["id"],
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn fingerprints_ignore_line_numbers_and_indentation() {
+        let todo = sample();
+        let mut moved = sample();
+        moved.line_number += 10;
+        moved.text_span.start_line += 10;
+        moved.body = format!("  {}", moved.body);
+
+        let before = report(&[todo], &[], &[], &[]);
+        let after = report(&[moved], &[], &[], &[]);
+        assert_eq!(fingerprints(&before), fingerprints(&after));
+    }
+
+    #[test]
+    fn identical_todos_have_their_own_fingerprints() {
+        let mut other_file = sample();
+        other_file.file_path = "src/main.rs".into();
+        let log = report(&[sample(), sample(), other_file], &[], &[], &[]);
+
+        let todos: Vec<_> = fingerprints(&log).into_iter().step_by(2).collect();
+        assert_eq!(todos.len(), 3);
+        assert_ne!(todos[0], todos[1]);
+        assert!(todos[0].ends_with(":1") && todos[1].ends_with(":2"));
+        assert!(todos[2].ends_with(":1"));
+    }
+
+    #[test]
+    fn rules_cover_every_diagnostic() {
+        let schema: Value = serde_json::from_str(crate::output::json::SCHEMA).unwrap();
+        for code in schema["$defs"]["diagnostic"]["properties"]["code"]["enum"]
+            .as_array()
+            .unwrap()
+        {
+            assert!(RULES.iter().any(|(id, _)| id == code), "{}", code);
+        }
+    }
+
+    #[test]
+    fn uris() {
+        assert_eq!(uri(Path::new("./src/main.rs")), "src/main.rs");
+        assert_eq!(uri(Path::new("../a b/c#.rs")), "../a%20b/c%23.rs");
+        assert_eq!(uri(Path::new("/tmp/x.rs")), "file:///tmp/x.rs");
+    }
+
+    #[test]
+    fn fnv() {
+        assert_eq!(fnv1a(""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a("a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a("foobar"), 0x85944171f73967e8);
+    }
+}
@@ -0,0 +1,1317 @@
+//! Parses the text of a TODO comment, starting at its marker:
+//!
+//! ```text
+//! todo      := MARKER [ "(" ref ("," ref)* ("," attr)* ")" ] [":"] text
+//!            | MARKER " " (shorthand | qualified) ":" text       (legacy)
+//! ref       := qualified | shorthand | "new" [ "=" quoted ] | owner | anything else (untracked)
+//! shorthand := an ID matching a provider's `id_pattern`, like TASK-123 or #123
+//! owner     := ["@"] handle                                      (alice, @alice, team-infra)
+//! qualified := "gh:" owner "/" repo "#" num | "gl:" group ("/" name)+ "#" num
+//!            | "jira:" KEY "-" num | "nt:" page-id
+//! attr      := ident | key "=" value ("," value)*
+//! key       := ident | "prop." ident
+//! value     := bare | '"' (char | '\\"' | '\\\\')* '"'
+//! ```
+//!
+//! Issue URLs in the text, like `https://github.com/org/repo/issues/42`, are references too;
+//! see [`url_references`].
+//!
+//! Only the first reference (after an owner, if there is one) can be `new`, or untracked; the
+//! ones after it have to be IDs or qualified, which is what sets them apart from attributes.
+//!
+//! Whitespace around commas and `=` is optional. A bare value right after `key=value` adds to
+//! it as a list (`labels=a, b`), unless the key takes a single value, or the bare value is a
+//! flag like `bidir`. Values are then typed by the [`Schema`](crate::attributes::Schema).
+//!
+//! Malformed input doesn't fail the parse. The TODO is still reported (as untracked, if the
+//! reference can't be read), and what's wrong with it is returned as diagnostics.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::attributes::Schema;
+use crate::ids::IdPatterns;
+use crate::lexer::Span;
+use crate::{TodoAttributeValue, TodoReference};
+
+/// A TODO, taken apart. Ranges are byte offsets into the parsed text.
+#[derive(Debug, PartialEq)]
+pub struct ParsedTodo<'a> {
+    /// The issues the TODO references, in order. Empty for untracked TODOs.
+    pub references: Vec<TodoReference>,
+    /// The person or team in `TODO(alice)` or `TODO(@team-infra)`, without the `@`
+    pub owner: Option<String>,
+    /// The `(...)` right after the marker, parentheses included, or the ID of a legacy
+    /// `TODO <ID>:`
+    pub reference_span: Option<Range<usize>>,
+    /// `None` when the TODO has no attributes at all
+    pub attributes: Option<HashMap<String, TodoAttributeValue>>,
+    /// The text after the marker, its `(...)` and an optional colon
+    pub message: &'a str,
+    pub diagnostics: Vec<(DiagnosticKind, Range<usize>)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    /// A `(` after the marker with no `)` to close it
+    UnterminatedParens,
+    /// A `"` inside the parentheses with no `"` to close it
+    UnterminatedQuote,
+    /// Nothing where the reference should be, as in `TODO():` or `TODO(, bidir):`
+    EmptyId,
+    /// A qualified reference that doesn't fit its tracker, like `gh:repo` or `jira:proj-1`
+    BadReference(String),
+    /// An attribute of the wrong type, or that couldn't be read at all
+    BadAttribute(String),
+    /// A key that isn't in the schema, with the known key it's probably a typo of
+    UnknownAttribute {
+        key: String,
+        suggestion: Option<String>,
+    },
+    /// A key the schema requires, which the TODO doesn't set
+    MissingAttribute(String),
+}
+
+impl DiagnosticKind {
+    /// A stable name for the kind of problem, for machine-readable output.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DiagnosticKind::UnterminatedParens => "unterminated-parens",
+            DiagnosticKind::UnterminatedQuote => "unterminated-quote",
+            DiagnosticKind::EmptyId => "empty-id",
+            DiagnosticKind::BadReference(_) => "bad-reference",
+            DiagnosticKind::BadAttribute(_) => "bad-attribute",
+            DiagnosticKind::UnknownAttribute { .. } => "unknown-attribute",
+            DiagnosticKind::MissingAttribute(_) => "missing-attribute",
+        }
+    }
+}
+
+impl std::fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiagnosticKind::UnterminatedParens => write!(f, "unterminated '('; expected ')'"),
+            DiagnosticKind::UnterminatedQuote => write!(f, "unterminated '\"'; expected '\"'"),
+            DiagnosticKind::EmptyId => write!(f, "missing ref; expected TODO(<ref>)"),
+            DiagnosticKind::BadReference(reason) => write!(f, "bad reference: {}", reason),
+            DiagnosticKind::BadAttribute(reason) => write!(f, "bad attribute: {}", reason),
+            DiagnosticKind::UnknownAttribute { key, suggestion } => match suggestion {
+                Some(suggestion) => write!(
+                    f,
+                    "unknown attribute '{}'; did you mean '{}'?",
+                    key, suggestion
+                ),
+                None => write!(f, "unknown attribute '{}'", key),
+            },
+            DiagnosticKind::MissingAttribute(key) => {
+                write!(f, "missing required attribute '{}'", key)
+            }
+        }
+    }
+}
+
+/// A problem with a TODO comment, and where in the file it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub span: Span,
+}
+
+/// Parses `text`, which starts with a marker `marker_len` bytes long.
+/// `ids` decides whether a reference is an issue ID (tracked) or anything else, like a name,
+/// and `schema` what type each attribute is.
+pub fn parse<'a>(
+    text: &'a str,
+    marker_len: usize,
+    ids: &IdPatterns,
+    schema: &Schema,
+) -> ParsedTodo<'a> {
+    let mut parsed = ParsedTodo {
+        references: Vec::new(),
+        owner: None,
+        reference_span: None,
+        attributes: None,
+        message: "",
+        diagnostics: Vec::new(),
+    };
+
+    let mut rest_start = marker_len;
+    if text[marker_len..].starts_with('(') {
+        let open = marker_len;
+        match find_unquoted(text, open..text.len(), ')') {
+            Some(close) => {
+                parse_parens(text, open + 1..close, ids, schema, &mut parsed);
+                parsed.reference_span = Some(open..close + 1);
+                rest_start = close + 1;
+            }
+            None => {
+                let diagnostic = match unclosed_quote(text, open..text.len()) {
+                    Some(quote) => (DiagnosticKind::UnterminatedQuote, quote..text.len()),
+                    None => (DiagnosticKind::UnterminatedParens, open..text.len()),
+                };
+                parsed.diagnostics.push(diagnostic);
+                rest_start = open + 1;
+            }
+        }
+    } else if let Some((id, reference)) = legacy_reference(text, marker_len, ids) {
+        parsed.references.push(reference);
+        rest_start = id.end;
+        parsed.reference_span = Some(id);
+    }
+
+    // `TODO: text`, or the looser `TODO - text`
+    let rest = text[rest_start..].trim_start();
+    parsed.message = rest
+        .strip_prefix(':')
+        .or_else(|| {
+            rest.strip_prefix('-')
+                .filter(|r| r.is_empty() || r.starts_with(' '))
+        })
+        .unwrap_or(rest)
+        .trim();
+    parsed
+}
+
+/// Parses what's between the parentheses: the references, then any attributes.
+fn parse_parens(
+    text: &str,
+    inside: Range<usize>,
+    ids: &IdPatterns,
+    schema: &Schema,
+    parsed: &mut ParsedTodo,
+) {
+    let (reference, mut attributes) = split_reference(text, inside.clone());
+
+    let id = text[reference.clone()].trim();
+    if id.is_empty() {
+        parsed
+            .diagnostics
+            .push((DiagnosticKind::EmptyId, reference));
+    } else if let Some(owner) = owner_handle(id, ids) {
+        parsed.owner = Some(owner.to_string());
+    } else {
+        push_reference(text, reference, ids, parsed);
+    }
+
+    // More references, up to the first item that isn't one
+    while let Some(rest) = attributes.clone() {
+        let (item, after) = split_reference(text, rest);
+        let id = text[item.clone()].trim();
+        if is_new(id) && !parsed.references.is_empty() {
+            parsed.diagnostics.push((
+                DiagnosticKind::BadReference(format!("'{}' has to be the first reference", id)),
+                trimmed(text, item),
+            ));
+        } else if is_new(id) || is_qualified(id) || ids.resolve(id).is_some() {
+            push_reference(text, item, ids, parsed);
+        } else {
+            break;
+        }
+        attributes = after;
+    }
+
+    let written = attributes.is_some();
+    let (mut values, spans) = match attributes {
+        Some(attributes) => parse_attributes(text, attributes, schema, &mut parsed.diagnostics),
+        None => Default::default(),
+    };
+    let parens = inside.start - 1..inside.end + 1;
+    schema.check(&mut values, &spans, parens, &mut parsed.diagnostics);
+    if written {
+        parsed.attributes = Some(values);
+    }
+}
+
+/// The ID in a legacy `TODO JIRA-123: ...`, if the TODO is written that way. Only IDs and
+/// qualified references count, so `TODO later: ...` is still an untracked TODO.
+fn legacy_reference(
+    text: &str,
+    marker_len: usize,
+    ids: &IdPatterns,
+) -> Option<(Range<usize>, TodoReference)> {
+    let after = &text[marker_len..];
+    let start = marker_len + (after.len() - after.trim_start_matches([' ', '\t']).len());
+    if start == marker_len {
+        return None;
+    }
+    let word_len = text[start..]
+        .find(char::is_whitespace)
+        .unwrap_or(text.len() - start);
+    let id = text[start..start + word_len].strip_suffix(':')?;
+
+    let reference = if is_qualified(id) {
+        parse_reference(id, ids).ok()?
+    } else {
+        ids.resolve(id)?
+    };
+    Some((start..start + id.len(), reference))
+}
+
+/// The issues linked from `text`: GitHub and GitLab issues and pull or merge requests, Jira
+/// issues (`.../browse/PROJ-1`), and Notion pages.
+pub fn url_references(text: &str) -> Vec<TodoReference> {
+    let mut references = Vec::new();
+    for word in text.split_whitespace() {
+        let Some(start) = word.find("https://").or_else(|| word.find("http://")) else {
+            continue;
+        };
+        let url = word[start..].trim_end_matches(['.', ',', ';', ':', ')', ']', '>', '"', '\'']);
+        if let Some(reference) = url_reference(url)
+            && !references.contains(&reference)
+        {
+            references.push(reference);
+        }
+    }
+    references
+}
+
+fn url_reference(url: &str) -> Option<TodoReference> {
+    let rest = url.split_once("://")?.1;
+    let rest = rest.split(['?', '#']).next()?;
+    let (host, path) = rest.split_once('/')?;
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    if host == "github.com" || host == "www.github.com" {
+        // org/repo/issues/42, or org/repo/pull/42
+        return match segments[..] {
+            [owner, repo, "issues" | "pull", number] => Some(TodoReference::GitHub {
+                repo: format!("{}/{}", owner, repo),
+                number: number.parse().ok().filter(|&n| n > 0)?,
+            }),
+            _ => None,
+        };
+    }
+    if host.starts_with("gitlab.") {
+        // group/sub/project/-/issues/77, or .../-/merge_requests/77
+        let dash = segments.iter().position(|&s| s == "-")?;
+        return match segments[dash..] {
+            ["-", "issues" | "merge_requests", number] if dash >= 2 => {
+                Some(TodoReference::GitLab {
+                    project: segments[..dash].join("/"),
+                    number: number.parse().ok().filter(|&n| n > 0)?,
+                })
+            }
+            _ => None,
+        };
+    }
+    if host == "notion.so" || host.ends_with(".notion.so") || host.ends_with(".notion.site") {
+        // The page ID is the last 32 hex digits of the last segment, after any title
+        let last = segments.last()?;
+        let page_id = last.get(last.len().checked_sub(32)?..)?;
+        return is_notion_id(page_id).then(|| TodoReference::Notion {
+            page_id: page_id.to_string(),
+        });
+    }
+    // Jira Cloud is at *.atlassian.net, but self-hosted Jira can be anywhere
+    match segments[..] {
+        [.., "browse", key] if is_jira_key(key) => Some(TodoReference::Jira {
+            key: key.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Reads the reference in `range`, keeping it if it's tracked, or reporting why it's malformed.
+fn push_reference(text: &str, range: Range<usize>, ids: &IdPatterns, parsed: &mut ParsedTodo) {
+    match parse_reference(text[range.clone()].trim(), ids) {
+        Ok(TodoReference::Untracked) => {}
+        Ok(reference) => parsed.references.push(reference),
+        Err(reason) => parsed
+            .diagnostics
+            .push((DiagnosticKind::BadReference(reason), trimmed(text, range))),
+    }
+}
+
+/// The handle in `alice` or `@alice`, if `id` is an owner rather than a reference.
+fn owner_handle<'a>(id: &'a str, ids: &IdPatterns) -> Option<&'a str> {
+    if is_new(id) || is_qualified(id) || ids.resolve(id).is_some() {
+        return None;
+    }
+    let handle = id.strip_prefix('@').unwrap_or(id);
+    let valid = handle.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+        && handle
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':' | '/'));
+    valid.then_some(handle)
+}
+
+/// `new` or `new="..."`: a request for a new issue.
+fn is_new(id: &str) -> bool {
+    id == "new"
+        || id
+            .strip_prefix("new")
+            .is_some_and(|rest| rest.trim_start().starts_with('='))
+}
+
+/// Whether `id` has one of the tracker prefixes, like `gh:` or `jira:`.
+fn is_qualified(id: &str) -> bool {
+    id.split_once(':')
+        .is_some_and(|(prefix, _)| QUALIFIED_PREFIXES.contains(&prefix))
+}
+
+const QUALIFIED_PREFIXES: [&str; 4] = ["gh", "gl", "jira", "nt"];
+
+/// Splits what's between the parentheses at the first comma: the first reference, and
+/// everything after it.
+fn split_reference(text: &str, inside: Range<usize>) -> (Range<usize>, Option<Range<usize>>) {
+    match find_unquoted(text, inside.clone(), ',') {
+        Some(comma) => (inside.start..comma, Some(comma + 1..inside.end)),
+        None => (inside, None),
+    }
+}
+
+/// The first `target` in `range` that isn't inside a double-quoted string.
+fn find_unquoted(text: &str, range: Range<usize>, target: char) -> Option<usize> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in text[range.clone()].char_indices() {
+        if escaped {
+            escaped = false;
+        } else if in_quotes && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == target && !in_quotes {
+            return Some(range.start + i);
+        }
+    }
+    None
+}
+
+/// Where the string that's still open at the end of `range` starts, if one is.
+fn unclosed_quote(text: &str, range: Range<usize>) -> Option<usize> {
+    let mut open = None;
+    let mut escaped = false;
+    for (i, c) in text[range.clone()].char_indices() {
+        if escaped {
+            escaped = false;
+        } else if open.is_some() && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            open = if open.is_some() {
+                None
+            } else {
+                Some(range.start + i)
+            };
+        }
+    }
+    open
+}
+
+/// The text of a double-quoted string, with `\"` and `\\` unescaped.
+fn unquote(text: &str) -> Option<String> {
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    let mut unquoted = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.push(chars.next()?),
+            '"' => return None,
+            c => unquoted.push(c),
+        }
+    }
+    Some(unquoted)
+}
+
+/// `text` as a double-quoted string, with `"` and `\` escaped: the inverse of `unquote`.
+pub fn quote(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}\"", escaped)
+}
+
+/// `text`, quoted only if it would read back differently bare: if it's empty, or has
+/// whitespace or any of `,()"\=` in it.
+pub fn quote_if_needed(text: &str) -> Cow<'_, str> {
+    let bare = !text.is_empty()
+        && !text
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, ',' | '(' | ')' | '"' | '\\' | '='));
+    if bare {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(quote(text))
+    }
+}
+
+/// Reads a reference: a qualified `gh:`, `gl:`, `jira:` or `nt:` one, a shorthand ID, or a
+/// request for a new issue. Anything else, like a person's name, is untracked.
+pub fn parse_reference(id: &str, ids: &IdPatterns) -> Result<TodoReference, String> {
+    if id == "new" {
+        return Ok(TodoReference::New { title: None });
+    }
+    if let Some(title) = id
+        .strip_prefix("new")
+        .and_then(|rest| rest.trim_start().strip_prefix('='))
+    {
+        let title = title.trim();
+        let title = if title.starts_with('"') {
+            unquote(title).ok_or_else(|| format!("{} is not a properly quoted title", title))?
+        } else {
+            title.to_string()
+        };
+        if title.trim().is_empty() {
+            return Err("new= needs a title, like new=\"Add retries\"".to_string());
+        }
+        return Ok(TodoReference::New { title: Some(title) });
+    }
+
+    let shorthand = || ids.resolve(id).unwrap_or(TodoReference::Untracked);
+    let Some((prefix, rest)) = id.split_once(':') else {
+        return Ok(shorthand());
+    };
+
+    match prefix {
+        "gh" => match path_and_number(rest) {
+            Some((repo, number)) if repo.split('/').count() == 2 => Ok(TodoReference::GitHub {
+                repo: repo.to_string(),
+                number,
+            }),
+            _ => Err(format!(
+                "'{}' is not a GitHub issue, like gh:org/repo#1234",
+                id
+            )),
+        },
+        "gl" => match path_and_number(rest) {
+            Some((project, number)) if project.contains('/') => Ok(TodoReference::GitLab {
+                project: project.to_string(),
+                number,
+            }),
+            _ => Err(format!(
+                "'{}' is not a GitLab issue, like gl:group/proj#77",
+                id
+            )),
+        },
+        "jira" if is_jira_key(rest) => Ok(TodoReference::Jira {
+            key: rest.to_string(),
+        }),
+        "jira" => Err(format!("'{}' is not a Jira issue, like jira:PROJ-456", id)),
+        "nt" if is_notion_id(rest) => Ok(TodoReference::Notion {
+            page_id: rest.to_string(),
+        }),
+        "nt" => Err(format!("'{}' is not a Notion page ID", id)),
+        _ => Ok(shorthand()),
+    }
+}
+
+/// Where the `new` or `new="..."` is in a `(...)`, for swapping it for the ID of the issue filed
+/// for it: `(alice, new="Add retries", labels=net)` becomes `(alice, TASK-12, labels=net)`.
+pub fn find_new_reference(parens: &str) -> Option<Range<usize>> {
+    if !parens.starts_with('(') || !parens.ends_with(')') || parens.len() < 2 {
+        return None;
+    }
+    split_unquoted(parens, 1..parens.len() - 1, ',')
+        .into_iter()
+        .map(|item| trimmed(parens, item))
+        .find(|item| is_new(&parens[item.clone()]))
+}
+
+/// How many comma-separated items there are in a `(...)`, where a list's values count one each.
+pub fn paren_items(parens: &str) -> usize {
+    if !parens.starts_with('(') || !parens.ends_with(')') || parens.len() < 2 {
+        return 0;
+    }
+    split_unquoted(parens, 1..parens.len() - 1, ',').len()
+}
+
+/// Splits `group/project#12` into the path and the issue number.
+fn path_and_number(text: &str) -> Option<(&str, u64)> {
+    let (path, number) = text.split_once('#')?;
+    let number = number.parse().ok().filter(|&n| n > 0)?;
+    let segments_ok = path.split('/').all(|segment| {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    });
+    segments_ok.then_some((path, number))
+}
+
+/// `PROJ-456`: an uppercase project key, a dash and a number.
+fn is_jira_key(text: &str) -> bool {
+    text.split_once('-').is_some_and(|(project, number)| {
+        project.starts_with(|c: char| c.is_ascii_uppercase())
+            && project
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+            && !number.is_empty()
+            && number.chars().all(|c| c.is_ascii_digit())
+    })
+}
+
+/// Notion page IDs are UUIDs, written with or without dashes.
+fn is_notion_id(text: &str) -> bool {
+    let digits: Vec<char> = text.chars().filter(|&c| c != '-').collect();
+    digits.len() == 32 && digits.iter().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Reads the attributes, untyped, along with where each one is written.
+fn parse_attributes(
+    text: &str,
+    range: Range<usize>,
+    schema: &Schema,
+    diagnostics: &mut Vec<(DiagnosticKind, Range<usize>)>,
+) -> (
+    HashMap<String, TodoAttributeValue>,
+    HashMap<String, Range<usize>>,
+) {
+    let mut attributes: HashMap<String, TodoAttributeValue> = HashMap::new();
+    let mut spans: HashMap<String, Range<usize>> = HashMap::new();
+    // The key of the `key=value` just before, which a bare value extends into a list
+    let mut list_key: Option<String> = None;
+
+    for item in split_unquoted(text, range, ',') {
+        let span = trimmed(text, item);
+        let attr = &text[span.clone()];
+        let mut bad = |reason: String| {
+            diagnostics.push((DiagnosticKind::BadAttribute(reason), span.clone()));
+        };
+        if attr.is_empty() {
+            bad("empty attribute".to_string());
+            continue;
+        }
+
+        match find_unquoted(attr, 0..attr.len(), '=') {
+            Some(eq) => {
+                list_key = None;
+                let key = attr[..eq].trim();
+                let raw = attr[eq + 1..].trim();
+                if key.is_empty() {
+                    bad(format!("'{}' has no key", attr));
+                } else if !is_key(key) {
+                    bad(format!("'{}' is not a valid attribute name", key));
+                } else if raw.is_empty() {
+                    bad(format!("'{}' has no value", key));
+                } else if attributes.contains_key(key) {
+                    bad(format!("'{}' is set more than once", key));
+                } else {
+                    match parse_value(raw) {
+                        Ok(value) => {
+                            attributes.insert(key.to_string(), TodoAttributeValue::Text(value));
+                            spans.insert(key.to_string(), span.clone());
+                            if schema.takes_list(key) {
+                                list_key = Some(key.to_string());
+                            }
+                        }
+                        Err(reason) => bad(format!("'{}': {}", key, reason)),
+                    }
+                }
+            }
+            None => match &list_key {
+                Some(key) if !schema.is_flag(attr) => match parse_value(attr) {
+                    Ok(value) => {
+                        let entry = attributes.get_mut(key).unwrap();
+                        match entry {
+                            TodoAttributeValue::List(values) => values.push(value),
+                            TodoAttributeValue::Text(first) => {
+                                *entry = TodoAttributeValue::List(vec![first.clone(), value]);
+                            }
+                            _ => {}
+                        }
+                        spans.get_mut(key).unwrap().end = span.end;
+                    }
+                    Err(reason) => bad(format!("'{}': {}", key, reason)),
+                },
+                _ => {
+                    list_key = None;
+                    if attributes.contains_key(attr) {
+                        bad(format!("'{}' is set more than once", attr));
+                    } else if is_key(attr) {
+                        attributes.insert(attr.to_string(), TodoAttributeValue::Flag(true));
+                        spans.insert(attr.to_string(), span.clone());
+                    } else {
+                        bad(format!("'{}' is not a valid attribute name", attr));
+                    }
+                }
+            },
+        }
+    }
+
+    (attributes, spans)
+}
+
+/// A value, either bare or double-quoted.
+fn parse_value(raw: &str) -> Result<String, String> {
+    if raw.starts_with('"') {
+        unquote(raw).ok_or_else(|| format!("{} is not a properly quoted value", raw))
+    } else if raw.contains('"') {
+        Err(format!("{} has a stray quote; quote the whole value", raw))
+    } else {
+        Ok(raw.to_string())
+    }
+}
+
+/// Splits `range` at every `separator` that isn't inside a double-quoted string.
+fn split_unquoted(text: &str, range: Range<usize>, separator: char) -> Vec<Range<usize>> {
+    let mut items = Vec::new();
+    let mut start = range.start;
+    while let Some(at) = find_unquoted(text, start..range.end, separator) {
+        items.push(start..at);
+        start = at + separator.len_utf8();
+    }
+    items.push(start..range.end);
+    items
+}
+
+/// `ident` or `prop.ident`, where an ident is letters, digits, `_` and `-`.
+fn is_key(key: &str) -> bool {
+    let ident = key.strip_prefix("prop.").unwrap_or(key);
+    ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && ident
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// `range`, without the whitespace at either end.
+fn trimmed(text: &str, range: Range<usize>) -> Range<usize> {
+    let slice = &text[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    let end = range.end - (slice.len() - slice.trim_end().len());
+    start..end.max(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::Date;
+
+    fn parse_todo(text: &str) -> ParsedTodo<'_> {
+        parse(
+            text,
+            "TODO".len(),
+            &IdPatterns::default(),
+            &Schema::default(),
+        )
+    }
+
+    #[test]
+    fn untracked() {
+        let parsed = parse_todo("TODO: Refactor this function");
+        assert_eq!(parsed.references, vec![]);
+        assert_eq!(parsed.reference_span, None);
+        assert_eq!(parsed.attributes, None);
+        assert_eq!(parsed.message, "Refactor this function");
+        assert!(parsed.diagnostics.is_empty());
+
+        assert_eq!(parse_todo("TODO fix this").message, "fix this");
+    }
+
+    #[test]
+    fn tracked() {
+        let text = "TODO(BUG-123): Refactor this function";
+        let parsed = parse_todo(text);
+        assert_eq!(
+            parsed.references,
+            vec![TodoReference::Tracked("BUG-123".to_string())]
+        );
+        assert_eq!(&text[parsed.reference_span.unwrap()], "(BUG-123)");
+        assert_eq!(parsed.message, "Refactor this function");
+    }
+
+    #[test]
+    fn person() {
+        let parsed = parse_todo("TODO(alice): Review this");
+        assert_eq!(parsed.references, vec![]);
+        assert_eq!(parsed.owner.as_deref(), Some("alice"));
+        assert!(parsed.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn owners() {
+        let owner = |text| parse_todo(text).owner;
+        assert_eq!(owner("TODO(@alice): x").as_deref(), Some("alice"));
+        assert_eq!(
+            owner("TODO(team-infra, bidir): x").as_deref(),
+            Some("team-infra")
+        );
+        assert_eq!(owner("TODO(team:infra): x").as_deref(), Some("team:infra"));
+        assert_eq!(owner("TODO(TASK-1): x"), None);
+        assert_eq!(owner("TODO(new): x"), None);
+        assert_eq!(owner("TODO(not a name): x"), None);
+        assert_eq!(owner("TODO: alice"), None);
+
+        let parsed = parse_todo("TODO(alice, TASK-1): x");
+        assert_eq!(parsed.owner.as_deref(), Some("alice"));
+        assert_eq!(
+            parsed.references,
+            vec![TodoReference::Tracked("TASK-1".to_string())]
+        );
+
+        // An owner's new issue
+        let parsed = parse_todo("TODO(alice, new): x");
+        assert_eq!(parsed.owner.as_deref(), Some("alice"));
+        assert_eq!(parsed.references, vec![TodoReference::New { title: None }]);
+        assert!(parsed.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn dash_separator() {
+        assert_eq!(parse_todo("TODO - handle errors").message, "handle errors");
+        assert_eq!(
+            parse_todo("TODO -1 is a bad default").message,
+            "-1 is a bad default"
+        );
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote_if_needed("urgent"), "urgent");
+        assert_eq!(quote_if_needed("In Progress"), "\"In Progress\"");
+        assert_eq!(quote_if_needed("a,b"), "\"a,b\"");
+        assert_eq!(quote_if_needed(""), "\"\"");
+        let tricky = r#"say "hi" \o/"#;
+        assert_eq!(unquote(&quote(tricky)).as_deref(), Some(tricky));
+    }
+
+    #[test]
+    fn no_message() {
+        assert_eq!(parse_todo("TODO(TASK-1)").message, "");
+    }
+
+    #[test]
+    fn attributes() {
+        let text = "TODO(TASK-123, bidir, labels=urgent,backend, assignee=alice): Fix bug";
+        let parsed = parse_todo(text);
+        assert_eq!(
+            parsed.references,
+            vec![TodoReference::Tracked("TASK-123".to_string())]
+        );
+        assert_eq!(
+            &text[parsed.reference_span.unwrap()],
+            "(TASK-123, bidir, labels=urgent,backend, assignee=alice)"
+        );
+        assert_eq!(parsed.message, "Fix bug");
+
+        let attributes = parsed.attributes.unwrap();
+        assert_eq!(attributes.len(), 3);
+        assert_eq!(attributes["bidir"], TodoAttributeValue::Flag(true));
+        assert_eq!(
+            attributes["labels"],
+            TodoAttributeValue::List(vec!["urgent".to_string(), "backend".to_string()])
+        );
+        assert_eq!(
+            attributes["assignee"],
+            TodoAttributeValue::Text("alice".to_string())
+        );
+    }
+
+    #[test]
+    fn qualified_references() {
+        let reference = |text| {
+            parse_todo(text)
+                .references
+                .pop()
+                .unwrap_or(TodoReference::Untracked)
+        };
+
+        assert_eq!(
+            reference("TODO(gh:org/repo#1234): x"),
+            TodoReference::GitHub {
+                repo: "org/repo".to_string(),
+                number: 1234
+            }
+        );
+        assert_eq!(
+            reference("TODO(gl:group/sub/proj#77, bidir): x"),
+            TodoReference::GitLab {
+                project: "group/sub/proj".to_string(),
+                number: 77
+            }
+        );
+        assert_eq!(
+            reference("TODO(jira:PROJ-456): x"),
+            TodoReference::Jira {
+                key: "PROJ-456".to_string()
+            }
+        );
+        assert_eq!(
+            reference("TODO(nt:25dc4188-fa0e-806c-9dd1-f4e7327751c5): x"),
+            TodoReference::Notion {
+                page_id: "25dc4188-fa0e-806c-9dd1-f4e7327751c5".to_string()
+            }
+        );
+        // Unknown prefixes aren't references we can check
+        assert_eq!(reference("TODO(team:infra): x"), TodoReference::Untracked);
+    }
+
+    #[test]
+    fn multiple_references() {
+        let text = "TODO(TASK-12, BUG-7, gh:org/repo#3, bidir, labels=a): blocked on both";
+        let parsed = parse_todo(text);
+        assert_eq!(
+            parsed.references,
+            vec![
+                TodoReference::Tracked("TASK-12".to_string()),
+                TodoReference::Tracked("BUG-7".to_string()),
+                TodoReference::GitHub {
+                    repo: "org/repo".to_string(),
+                    number: 3
+                },
+            ]
+        );
+        let attributes = parsed.attributes.unwrap();
+        assert_eq!(attributes["bidir"], TodoAttributeValue::Flag(true));
+        assert!(!attributes.contains_key("BUG-7"));
+        assert!(parsed.diagnostics.is_empty());
+
+        // Each one is checked on its own
+        let text = "TODO(TASK-12, jira:bad, new): x";
+        let parsed = parse_todo(text);
+        assert_eq!(
+            parsed.references,
+            vec![TodoReference::Tracked("TASK-12".to_string())]
+        );
+        let problems: Vec<_> = parsed
+            .diagnostics
+            .iter()
+            .map(|(kind, span)| (kind.to_string(), &text[span.clone()]))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    "bad reference: 'jira:bad' is not a Jira issue, like jira:PROJ-456".to_string(),
+                    "jira:bad"
+                ),
+                (
+                    "bad reference: 'new' has to be the first reference".to_string(),
+                    "new"
+                ),
+            ]
+        );
+
+        // A person first, then the ticket
+        assert_eq!(
+            parse_todo("TODO(alice, TASK-1): x").references,
+            vec![TodoReference::Tracked("TASK-1".to_string())]
+        );
+    }
+
+    #[test]
+    fn legacy_ids() {
+        let text = "TODO JIRA-123: fix the flaky test";
+        let parsed = parse_todo(text);
+        assert_eq!(
+            parsed.references,
+            vec![TodoReference::Tracked("JIRA-123".to_string())]
+        );
+        assert_eq!(&text[parsed.reference_span.unwrap()], "JIRA-123");
+        assert_eq!(parsed.message, "fix the flaky test");
+
+        let parsed = parse_todo("TODO gh:org/repo#4: qualified");
+        assert_eq!(
+            parsed.references,
+            vec![TodoReference::GitHub {
+                repo: "org/repo".to_string(),
+                number: 4
+            }]
+        );
+        assert_eq!(parsed.message, "qualified");
+
+        // Words that aren't IDs, and IDs without the colon, are just text
+        for text in ["TODO later: x", "TODO JIRA-123 fix it", "TODO: JIRA-123: x"] {
+            let parsed = parse_todo(text);
+            assert_eq!(parsed.references, vec![], "{}", text);
+            assert_eq!(parsed.reference_span, None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn urls() {
+        let references = url_references(
+            "see https://github.com/org/repo/issues/42, \
+             (https://gitlab.com/group/sub/proj/-/merge_requests/7) and \
+             https://acme.atlassian.net/browse/PROJ-456?focus=1. Also \
+             https://www.notion.so/acme/Fix-login-25dc4188fa0e806c9dd1f4e7327751c5?pvs=4 \
+             and https://github.com/org/repo/issues/42 again",
+        );
+        assert_eq!(
+            references,
+            vec![
+                TodoReference::GitHub {
+                    repo: "org/repo".to_string(),
+                    number: 42
+                },
+                TodoReference::GitLab {
+                    project: "group/sub/proj".to_string(),
+                    number: 7
+                },
+                TodoReference::Jira {
+                    key: "PROJ-456".to_string()
+                },
+                TodoReference::Notion {
+                    page_id: "25dc4188fa0e806c9dd1f4e7327751c5".to_string()
+                },
+            ]
+        );
+
+        for text in [
+            "https://github.com/org/repo",
+            "https://github.com/org/repo/issues/0",
+            "https://gitlab.com/proj/-/issues/3",
+            "https://example.com/browse/not-a-key",
+            "https://www.notion.so/acme/Fix-login",
+            "github.com/org/repo/issues/42",
+        ] {
+            assert_eq!(url_references(text), vec![], "{}", text);
+        }
+    }
+
+    #[test]
+    fn bad_qualified_references() {
+        for text in [
+            "TODO(gh:repo#1): x",
+            "TODO(gh:org/repo): x",
+            "TODO(gh:org/repo#0): x",
+            "TODO(gl:proj#7): x",
+            "TODO(jira:proj-4): x",
+            "TODO(nt:not-a-page): x",
+        ] {
+            let parsed = parse_todo(text);
+            assert_eq!(parsed.references, vec![], "{}", text);
+            assert!(
+                matches!(
+                    parsed.diagnostics[..],
+                    [(DiagnosticKind::BadReference(_), _)]
+                ),
+                "{}",
+                text
+            );
+        }
+
+        let text = "TODO( gh:org#1 ): x";
+        let (kind, span) = &parse_todo(text).diagnostics[0];
+        assert_eq!(&text[span.clone()], "gh:org#1");
+        assert_eq!(
+            kind.to_string(),
+            "bad reference: 'gh:org#1' is not a GitHub issue, like gh:org/repo#1234"
+        );
+    }
+
+    #[test]
+    fn new_issues() {
+        let parsed = parse_todo("TODO(new): add retries");
+        assert_eq!(parsed.references, vec![TodoReference::New { title: None }]);
+        assert_eq!(parsed.message, "add retries");
+
+        let text = r#"TODO(new="Retries, with \"backoff\"", labels=net): add (some) retries"#;
+        let parsed = parse_todo(text);
+        assert_eq!(
+            parsed.references,
+            vec![TodoReference::New {
+                title: Some(r#"Retries, with "backoff""#.to_string())
+            }]
+        );
+        assert_eq!(
+            parsed.attributes.unwrap()["labels"],
+            TodoAttributeValue::List(vec!["net".to_string()])
+        );
+        assert_eq!(parsed.message, "add (some) retries");
+
+        // Quoted parentheses don't close the reference
+        let parsed = parse_todo(r#"TODO(new="Handle (rare) errors"): x"#);
+        assert_eq!(
+            parsed.references,
+            vec![TodoReference::New {
+                title: Some("Handle (rare) errors".to_string())
+            }]
+        );
+        assert_eq!(parsed.message, "x");
+    }
+
+    #[test]
+    fn bad_new_issues() {
+        for text in [
+            r#"TODO(new=""): x"#,
+            r#"TODO(new="a" b): x"#,
+            "TODO(new=): x",
+        ] {
+            let parsed = parse_todo(text);
+            assert!(
+                matches!(
+                    parsed.diagnostics[..],
+                    [(DiagnosticKind::BadReference(_), _)]
+                ),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn finding_new_references() {
+        let replaced = |parens: &str| {
+            let mut parens = parens.to_string();
+            let new = find_new_reference(&parens)?;
+            parens.replace_range(new, "TASK-12");
+            Some(parens)
+        };
+        assert_eq!(replaced("(new)").as_deref(), Some("(TASK-12)"));
+        assert_eq!(
+            replaced(r#"(new="a, b", labels=net, bidir)"#).as_deref(),
+            Some("(TASK-12, labels=net, bidir)")
+        );
+        assert_eq!(
+            replaced("(alice,  new , bidir)").as_deref(),
+            Some("(alice,  TASK-12 , bidir)")
+        );
+        assert_eq!(replaced("(TASK-1, newer)"), None);
+        assert_eq!(replaced("new"), None);
+    }
+
+    fn attributes_of(text: &str) -> HashMap<String, TodoAttributeValue> {
+        let parsed = parse_todo(text);
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        parsed.attributes.unwrap()
+    }
+
+    fn text(value: &str) -> TodoAttributeValue {
+        TodoAttributeValue::Text(value.to_string())
+    }
+
+    fn list(values: &[&str]) -> TodoAttributeValue {
+        TodoAttributeValue::List(values.iter().map(|v| v.to_string()).collect())
+    }
+
+    #[test]
+    fn quoted_values() {
+        let attributes = attributes_of(
+            r#"TODO(TASK-1, status="In Progress", section="parser, lexer", note="say \"hi\" \\o/"): x"#,
+        );
+        assert_eq!(attributes["status"], text("In Progress"));
+        // A quoted comma doesn't split the value, or anything after it
+        assert_eq!(attributes["section"], text("parser, lexer"));
+        assert_eq!(attributes["note"], text(r#"say "hi" \o/"#));
+        assert_eq!(attributes.len(), 3);
+    }
+
+    #[test]
+    fn whitespace_around_commas_is_optional() {
+        let attributes = attributes_of("TODO(TASK-1,bidir ,assignee = alice,due=2025-10-01): x");
+        assert_eq!(attributes["bidir"], TodoAttributeValue::Flag(true));
+        assert_eq!(attributes["assignee"], text("alice"));
+        assert_eq!(
+            attributes["due"],
+            TodoAttributeValue::Date(Date::parse("2025-10-01").unwrap())
+        );
+    }
+
+    #[test]
+    fn lists_end_at_the_next_attribute() {
+        for text in [
+            "TODO(TASK-1, labels=urgent,backend, assignee=alice): x",
+            "TODO(TASK-1, labels=urgent, backend,assignee=alice): x",
+            r#"TODO(TASK-1, labels="urgent", backend, assignee=alice): x"#,
+        ] {
+            let attributes = attributes_of(text);
+            assert_eq!(
+                attributes["labels"],
+                list(&["urgent", "backend"]),
+                "{}",
+                text
+            );
+            assert_eq!(attributes["assignee"], self::text("alice"), "{}", text);
+        }
+
+        // Sync flags are never list values, and end the list
+        let attributes = attributes_of("TODO(TASK-1, labels=a,b, bidir, wip): x");
+        assert_eq!(attributes["labels"], list(&["a", "b"]));
+        assert_eq!(attributes["bidir"], TodoAttributeValue::Flag(true));
+        assert_eq!(attributes["wip"], TodoAttributeValue::Flag(true));
+
+        // Quoted list items can hold commas
+        let attributes = attributes_of(r#"TODO(TASK-1, labels="a, b",c): x"#);
+        assert_eq!(attributes["labels"], list(&["a, b", "c"]));
+    }
+
+    #[test]
+    fn single_values_end_at_the_next_item() {
+        let attributes =
+            attributes_of("TODO(TASK-1, assignee=alice, wip, status=open, bidir=false): x");
+        assert_eq!(attributes["assignee"], text("alice"));
+        assert_eq!(attributes["wip"], TodoAttributeValue::Flag(true));
+        assert_eq!(attributes["bidir"], TodoAttributeValue::Flag(false));
+    }
+
+    #[test]
+    fn attributes_are_checked_against_the_schema() {
+        let text = "TODO(TASK-1, asignee=bob, due=next week, bidir=yes): x";
+        let parsed = parse_todo(text);
+        let problems: Vec<_> = parsed
+            .diagnostics
+            .iter()
+            .map(|(kind, span)| (kind.to_string(), &text[span.clone()]))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    "unknown attribute 'asignee'; did you mean 'assignee'?".to_string(),
+                    "asignee=bob"
+                ),
+                (
+                    "bad attribute: 'due' is a date, like 2025-10-01, not 'next week'".to_string(),
+                    "due=next week"
+                ),
+                (
+                    "bad attribute: 'bidir' is a flag; write bidir or bidir=false".to_string(),
+                    "bidir=yes"
+                ),
+            ]
+        );
+
+        let config = serde_yaml::from_str(
+            "keys: { priority: { type: enum, values: [low, high], required: true } }",
+        )
+        .unwrap();
+        let schema = Schema::new(&config).unwrap();
+        let text = "TODO(TASK-1, labels=a): x";
+        let parsed = parse(text, "TODO".len(), &IdPatterns::default(), &schema);
+        assert_eq!(
+            parsed.diagnostics,
+            vec![(
+                DiagnosticKind::MissingAttribute("priority".to_string()),
+                4..text.len() - 3
+            )]
+        );
+        // Even without any attributes
+        let parsed = parse(
+            "TODO(TASK-1): x",
+            "TODO".len(),
+            &IdPatterns::default(),
+            &schema,
+        );
+        assert_eq!(parsed.attributes, None);
+        assert_eq!(parsed.diagnostics.len(), 1);
+        // Plain TODOs have nowhere to put attributes
+        assert!(
+            parse("TODO: x", "TODO".len(), &IdPatterns::default(), &schema)
+                .diagnostics
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn bad_quotes() {
+        let text = r#"TODO(TASK-1, status="In Progress): x"#;
+        let parsed = parse_todo(text);
+        assert_eq!(
+            parsed.diagnostics,
+            vec![(DiagnosticKind::UnterminatedQuote, 20..text.len())]
+        );
+
+        let parsed = parse_todo(r#"TODO(TASK-1, status=In"Progress", owner="a"b): x"#);
+        let reasons: Vec<_> = parsed
+            .diagnostics
+            .iter()
+            .map(|(kind, _)| kind.to_string())
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                r#"bad attribute: 'status': In"Progress" has a stray quote; quote the whole value"#,
+                r#"bad attribute: 'owner': "a"b is not a properly quoted value"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_id() {
+        let text = "TODO(): Empty parens";
+        let parsed = parse_todo(text);
+        assert_eq!(parsed.references, vec![]);
+        assert_eq!(parsed.diagnostics, vec![(DiagnosticKind::EmptyId, 5..5)]);
+        assert_eq!(parsed.message, "Empty parens");
+
+        let parsed = parse_todo("TODO( , bidir): x");
+        assert_eq!(parsed.diagnostics[0].0, DiagnosticKind::EmptyId);
+        assert_eq!(
+            parsed.attributes.unwrap()["bidir"],
+            TodoAttributeValue::Flag(true)
+        );
+    }
+
+    #[test]
+    fn unterminated_parens() {
+        let text = "TODO(TASK-1: forgot to close";
+        let parsed = parse_todo(text);
+        assert_eq!(parsed.references, vec![]);
+        assert_eq!(parsed.reference_span, None);
+        assert_eq!(
+            parsed.diagnostics,
+            vec![(DiagnosticKind::UnterminatedParens, 4..text.len())]
+        );
+    }
+
+    #[test]
+    fn bad_attributes() {
+        let text = "TODO(TASK-1, due=, =high, not valid, , prop.Status=Done): x";
+        let parsed = parse_todo(text);
+
+        let problems: Vec<_> = parsed
+            .diagnostics
+            .iter()
+            .map(|(kind, span)| (kind.to_string(), &text[span.clone()]))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                ("bad attribute: 'due' has no value".to_string(), "due="),
+                ("bad attribute: '=high' has no key".to_string(), "=high"),
+                (
+                    "bad attribute: 'not valid' is not a valid attribute name".to_string(),
+                    "not valid"
+                ),
+                ("bad attribute: empty attribute".to_string(), ""),
+            ]
+        );
+
+        // Good attributes are still kept
+        let attributes = parsed.attributes.unwrap();
+        assert_eq!(
+            attributes["prop.Status"],
+            TodoAttributeValue::Text("Done".to_string())
+        );
+        assert_eq!(
+            parsed.references,
+            vec![TodoReference::Tracked("TASK-1".to_string())]
+        );
+    }
+
+    #[test]
+    fn duplicate_attributes() {
+        let text = "TODO(TASK-1, p=1, labels=a,b, p=2, bidir, labels=c, bidir): x";
+        let parsed = parse_todo(text);
+
+        let problems: Vec<_> = parsed
+            .diagnostics
+            .iter()
+            .map(|(kind, span)| (kind.to_string(), &text[span.clone()]))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (
+                    "bad attribute: 'p' is set more than once".to_string(),
+                    "p=2"
+                ),
+                (
+                    "bad attribute: 'labels' is set more than once".to_string(),
+                    "labels=c"
+                ),
+                (
+                    "bad attribute: 'bidir' is set more than once".to_string(),
+                    "bidir"
+                ),
+            ]
+        );
+
+        // The first value is the one that's kept
+        let attributes = parsed.attributes.unwrap();
+        assert_eq!(attributes["p"], TodoAttributeValue::Text("1".to_string()));
+        assert_eq!(
+            attributes["labels"],
+            TodoAttributeValue::List(vec!["a".to_string(), "b".to_string()])
+        );
+    }
+}
@@ -1,5 +1,28 @@
+use std::collections::HashMap;
 use std::error::Error;
 
+use crate::TodoAttributeValue;
+
+/// An issue to file for a `TODO(new)` comment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewIssue {
+    pub title: String,
+    /// Who to assign the issue to, as a user of the provider
+    pub assignee: Option<String>,
+    /// The TODO's attributes, which providers set where they're configured to go
+    pub attributes: HashMap<String, TodoAttributeValue>,
+}
+
+/// Where an issue a TODO references stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueStatus {
+    /// The tracker doesn't have it
+    Missing,
+    Open,
+    /// Done, or otherwise finished with, so the TODO is probably stale
+    Closed,
+}
+
 #[async_trait::async_trait]
 pub trait IssueProvider {
     type Error: Error + Send + Sync + 'static;
@@ -7,6 +30,19 @@ pub trait IssueProvider {
     // MVP: just check existence
     async fn issue_exists(&self, id: &str) -> Result<bool, Self::Error>;
 
+    /// Whether an issue exists, and if so whether it's been closed. Providers that can't tell
+    /// take every issue that exists to be open.
+    async fn issue_status(&self, id: &str) -> Result<IssueStatus, Self::Error> {
+        Ok(if self.issue_exists(id).await? {
+            IssueStatus::Open
+        } else {
+            IssueStatus::Missing
+        })
+    }
+
+    /// Files a new issue, returning the reference to write into the TODO, e.g. `TASK-12`.
+    async fn create_issue(&self, issue: &NewIssue) -> Result<String, Self::Error>;
+
     // Future: full issue data
     // async fn get_issue(&self, id: &str) -> Result<Issue, Self::Error>;
 }
@@ -1,7 +1,9 @@
 #![allow(dead_code)]
 
-use super::IssueProvider;
-use crate::NotionConfig;
+use super::{IssueProvider, IssueStatus, NewIssue};
+use std::collections::HashMap;
+
+use crate::{NotionConfig, TodoAttributeValue};
 use async_trait::async_trait;
 
 // What we send to Notion
@@ -33,6 +35,10 @@ pub enum NotionError {
     Http(reqwest::Error),
     Auth,
     NotFound,
+    /// Notion answered, but not with what we expected
+    BadResponse(String),
+    /// An ID that isn't a `<prefix>-<number>` unique ID, which is all Notion can look up
+    UnsupportedId(String),
 }
 
 impl std::fmt::Display for NotionError {
@@ -41,6 +47,12 @@ impl std::fmt::Display for NotionError {
             NotionError::Http(e) => write!(f, "HTTP error: {}", e),
             NotionError::Auth => write!(f, "Authentication failed"),
             NotionError::NotFound => write!(f, "Page not found"),
+            NotionError::BadResponse(message) => write!(f, "Unexpected response: {}", message),
+            NotionError::UnsupportedId(id) => write!(
+                f,
+                "can't look up '{}'; Notion finds issues by a unique ID like TASK-12",
+                id
+            ),
         }
     }
 }
@@ -50,12 +62,21 @@ impl std::error::Error for NotionError {}
 pub struct NotionProvider {
     client: reqwest::Client,
     database_id: String,
+    title_property: String,
+    assignee_property: Option<String>,
+    status_property: Option<String>,
+    closed_statuses: Vec<String>,
+    properties: HashMap<String, String>,
 }
 
 impl NotionProvider {
     pub fn new(config: &NotionConfig) -> Result<Self, NotionError> {
         // Get token from environment
         let token = std::env::var("NOTION_TOKEN").map_err(|_| NotionError::Auth)?;
+        // `NOTION_TOKEN=` is how a token from `.env` is turned off
+        if token.is_empty() {
+            return Err(NotionError::Auth);
+        }
 
         // Build client (same as before)
         let mut headers = reqwest::header::HeaderMap::new();
@@ -77,8 +98,56 @@ impl NotionProvider {
         Ok(Self {
             client,
             database_id: config.database_id.clone(), // Store the database ID
+            title_property: config.title_property.clone(),
+            assignee_property: config.assignee_property.clone(),
+            status_property: config.status_property.clone(),
+            closed_statuses: config.closed_statuses.clone(),
+            properties: config.properties.clone(),
         })
     }
+
+    /// Where a page stands, for `nt:PAGE_ID` references that name a page directly.
+    pub async fn page_status(&self, page_id: &str) -> Result<IssueStatus, NotionError> {
+        let url = format!("https://api.notion.com/v1/pages/{}", page_id);
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(NotionError::Http)?;
+
+        match response.status() {
+            reqwest::StatusCode::OK => {
+                let page: serde_json::Value = response.json().await.map_err(NotionError::Http)?;
+                Ok(self.status_of(&page))
+            }
+            reqwest::StatusCode::NOT_FOUND => Ok(IssueStatus::Missing),
+            _ => Err(NotionError::Auth),
+        }
+    }
+
+    /// Whether a page that exists has been closed, going by its `status_property`.
+    fn status_of(&self, page: &serde_json::Value) -> IssueStatus {
+        let Some(property) = &self.status_property else {
+            return IssueStatus::Open;
+        };
+        let value = &page["properties"][property.as_str()];
+        // Status and select properties both have a named option, or null when unset
+        let name = value["status"]["name"]
+            .as_str()
+            .or_else(|| value["select"]["name"].as_str());
+        match name {
+            Some(name)
+                if self
+                    .closed_statuses
+                    .iter()
+                    .any(|closed| closed.eq_ignore_ascii_case(name)) =>
+            {
+                IssueStatus::Closed
+            }
+            _ => IssueStatus::Open,
+        }
+    }
 }
 
 #[async_trait]
@@ -86,6 +155,10 @@ impl IssueProvider for NotionProvider {
     type Error = NotionError;
 
     async fn issue_exists(&self, id: &str) -> Result<bool, Self::Error> {
+        Ok(self.issue_status(id).await? != IssueStatus::Missing)
+    }
+
+    async fn issue_status(&self, id: &str) -> Result<IssueStatus, Self::Error> {
         // println!("Printing DB schema {}", id);
         // let schema_url = format!("https://api.notion.com/v1/databases/{}", self.database_id);
         // println!("Getting database schema from: {}", schema_url);
@@ -100,19 +173,9 @@ impl IssueProvider for NotionProvider {
 
         // println!("Checking existence of task with ID: {}", id);
 
-        let number = if let Some(dash_pos) = id.find('-') {
-            let number_str = &id[dash_pos + 1..];
-            match number_str.parse::<i32>() {
-                Ok(num) => num,
-                Err(_) => {
-                    // println!("Could not parse number from ID: {}", id);
-                    return Ok(false);
-                }
-            }
-        } else {
-            println!("ID format doesn't contain dash: {}", id);
-            return Ok(false);
-        };
+        // An `id_pattern` can let through IDs that can't be looked up; those aren't unknown
+        let number =
+            unique_id_number(id).ok_or_else(|| NotionError::UnsupportedId(id.to_string()))?;
 
         // We need to know which database to query and which property contains the ID
         // For now, let's hardcode - we'll make this configurable later
@@ -150,11 +213,14 @@ impl IssueProvider for NotionProvider {
                     response.json().await.map_err(NotionError::Http)?;
 
                 // println!("Found {} results for {}", query_result.results.len(), id);
-                Ok(!query_result.results.is_empty())
+                Ok(query_result
+                    .results
+                    .first()
+                    .map_or(IssueStatus::Missing, |page| self.status_of(page)))
             }
             reqwest::StatusCode::BAD_REQUEST => {
                 // 400 - Bad request format, let's see what's wrong
-                let error_text = response.text().await.map_err(NotionError::Http)?;
+                let _error_text = response.text().await.map_err(NotionError::Http)?;
                 // println!("400 Bad Request for {}: {}", id, error_text);
                 Err(NotionError::Auth) // This is actually a query format error, not auth
             }
@@ -165,13 +231,172 @@ impl IssueProvider for NotionProvider {
             reqwest::StatusCode::NOT_FOUND => {
                 // This probably won't happen for database queries, but just in case
                 // println!("404 Not Found for {}", id);
-                Ok(false)
+                Ok(IssueStatus::Missing)
             }
-            status => {
-                let error_text = response.text().await.map_err(NotionError::Http)?;
+            _status => {
+                let _error_text = response.text().await.map_err(NotionError::Http)?;
                 // println!("Unexpected status {} for {}: {}", status, id, error_text);
                 Err(NotionError::Auth) // Temporary - we'll improve this later
             }
         }
     }
+
+    async fn create_issue(&self, issue: &NewIssue) -> Result<String, Self::Error> {
+        let mut page = serde_json::json!({
+            "parent": { "database_id": self.database_id },
+            "properties": {
+                self.title_property.as_str(): {
+                    "title": [{ "text": { "content": issue.title } }]
+                }
+            }
+        });
+        // Notion users are assigned by ID, through a people property
+        if let (Some(property), Some(assignee)) = (&self.assignee_property, &issue.assignee) {
+            page["properties"][property.as_str()] =
+                serde_json::json!({ "people": [{ "object": "user", "id": assignee }] });
+        }
+        for (key, value) in &issue.attributes {
+            if let Some(property) = self.properties.get(key) {
+                page["properties"][property.as_str()] = property_value(value);
+            }
+        }
+
+        let response = self
+            .client
+            .post("https://api.notion.com/v1/pages")
+            .json(&page)
+            .send()
+            .await
+            .map_err(NotionError::Http)?;
+
+        match response.status() {
+            reqwest::StatusCode::OK => {
+                let created: serde_json::Value =
+                    response.json().await.map_err(NotionError::Http)?;
+                // The same ID property `issue_exists` queries, e.g. {"prefix": "TASK", "number": 12}
+                let unique_id = &created["properties"]["ID"]["unique_id"];
+                match (unique_id["prefix"].as_str(), unique_id["number"].as_u64()) {
+                    (Some(prefix), Some(number)) => Ok(format!("{}-{}", prefix, number)),
+                    _ => Err(NotionError::BadResponse(
+                        "created page has no prefixed ID property".to_string(),
+                    )),
+                }
+            }
+            reqwest::StatusCode::UNAUTHORIZED => Err(NotionError::Auth),
+            status => {
+                let error_text = response.text().await.map_err(NotionError::Http)?;
+                Err(NotionError::BadResponse(format!(
+                    "{}: {}",
+                    status, error_text
+                )))
+            }
+        }
+    }
+}
+
+/// An attribute's value as a Notion property value: lists are multi-select options, text a
+/// select option, dates dates, numbers numbers, and flags checkboxes.
+fn property_value(value: &TodoAttributeValue) -> serde_json::Value {
+    match value {
+        TodoAttributeValue::Flag(flag) => serde_json::json!({ "checkbox": flag }),
+        TodoAttributeValue::Text(text) => serde_json::json!({ "select": { "name": text } }),
+        TodoAttributeValue::List(values) => {
+            let options: Vec<_> = values
+                .iter()
+                .map(|name| serde_json::json!({ "name": name }))
+                .collect();
+            serde_json::json!({ "multi_select": options })
+        }
+        TodoAttributeValue::Date(date) => {
+            serde_json::json!({ "date": { "start": date.to_string() } })
+        }
+        TodoAttributeValue::Number(number) => serde_json::json!({ "number": number }),
+    }
+}
+
+/// The number of a `<prefix>-<number>` unique ID, like the 12 in `TASK-12`, which is what a
+/// database is queried by.
+fn unique_id_number(id: &str) -> Option<u64> {
+    let (prefix, number) = id.rsplit_once('-')?;
+    if prefix.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn with_status(status_property: Option<&str>) -> NotionProvider {
+        NotionProvider {
+            client: reqwest::Client::new(),
+            database_id: String::new(),
+            title_property: "Name".to_string(),
+            assignee_property: None,
+            status_property: status_property.map(str::to_string),
+            closed_statuses: vec!["Done".to_string(), "Won't do".to_string()],
+            properties: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn statuses() {
+        let page = |property: Value| json!({ "properties": { "Status": property } });
+        let status = |name: &str| page(json!({ "status": { "name": name } }));
+
+        let provider = with_status(Some("Status"));
+        assert_eq!(provider.status_of(&status("done")), IssueStatus::Closed);
+        assert_eq!(provider.status_of(&status("Won't do")), IssueStatus::Closed);
+        assert_eq!(
+            provider.status_of(&status("In progress")),
+            IssueStatus::Open
+        );
+        assert_eq!(
+            provider.status_of(&page(json!({ "select": { "name": "Done" } }))),
+            IssueStatus::Closed
+        );
+        assert_eq!(
+            provider.status_of(&page(json!({ "select": null }))),
+            IssueStatus::Open
+        );
+        // Without a status property, nothing is ever closed
+        assert_eq!(
+            with_status(None).status_of(&status("Done")),
+            IssueStatus::Open
+        );
+    }
+
+    #[test]
+    fn property_values() {
+        let labels = TodoAttributeValue::List(vec!["net".to_string(), "io".to_string()]);
+        assert_eq!(
+            property_value(&labels),
+            json!({ "multi_select": [{ "name": "net" }, { "name": "io" }] })
+        );
+        assert_eq!(
+            property_value(&TodoAttributeValue::Text("high".to_string())),
+            json!({ "select": { "name": "high" } })
+        );
+        let due = crate::attributes::Date::parse("2025-10-01").unwrap();
+        assert_eq!(
+            property_value(&TodoAttributeValue::Date(due)),
+            json!({ "date": { "start": "2025-10-01" } })
+        );
+        assert_eq!(
+            property_value(&TodoAttributeValue::Number(3)),
+            json!({ "number": 3 })
+        );
+    }
+
+    #[test]
+    fn unique_id_numbers() {
+        assert_eq!(unique_id_number("TASK-12"), Some(12));
+        assert_eq!(unique_id_number("eng-ops-7"), Some(7));
+        for id in ["TASK12", "#12", "-12", "TASK-", "TASK-+1", "TASK-1a"] {
+            assert_eq!(unique_id_number(id), None, "{}", id);
+        }
+    }
 }
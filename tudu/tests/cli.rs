@@ -1,17 +1,24 @@
 use assert_cmd::Command;
 use insta::assert_snapshot;
-use std::env;
 use std::path::PathBuf;
 
 fn repo_fixture() -> PathBuf {
     PathBuf::from("tests/fixtures/repo")
 }
 
+/// A `tudu` command with an empty Notion token, so runs don't depend on the local `.env` or on
+/// reaching Notion: references aren't checked, which is a warning.
+fn tudu() -> Command {
+    let mut cmd = Command::cargo_bin("tudu").unwrap();
+    cmd.env("NOTION_TOKEN", "");
+    cmd
+}
+
 #[test]
 fn snapshot_normal() {
     let fixture_dir = repo_fixture();
 
-    let mut cmd = Command::cargo_bin("tudu").unwrap();
+    let mut cmd = tudu();
     cmd.arg(&fixture_dir);
     let output = cmd.output().unwrap();
     let stdout = String::from_utf8_lossy(&output.stdout);
@@ -23,10 +30,456 @@ fn snapshot_normal() {
 fn snapshot_verbose() {
     let fixture_dir = repo_fixture();
 
-    let mut cmd = Command::cargo_bin("tudu").unwrap();
+    let mut cmd = tudu();
     cmd.arg(&fixture_dir).arg("--verbose");
     let output = cmd.output().unwrap();
     let stdout = String::from_utf8_lossy(&output.stdout);
 
     assert_snapshot!("run_verbose", stdout);
 }
+
+#[test]
+fn output_is_independent_of_thread_count() {
+    let fixture_dir = repo_fixture();
+
+    let outputs: Vec<_> = ["1", "2", "8"]
+        .iter()
+        .map(|jobs| {
+            let mut cmd = tudu();
+            cmd.arg(&fixture_dir)
+                .arg("--verbose")
+                .arg("--jobs")
+                .arg(jobs);
+            cmd.output().unwrap().stdout
+        })
+        .collect();
+
+    assert_eq!(outputs[0], outputs[1]);
+    assert_eq!(outputs[0], outputs[2]);
+}
+
+#[test]
+fn snapshot_languages() {
+    let mut cmd = tudu();
+    cmd.arg("tests/fixtures/languages").arg("--verbose");
+    let output = cmd.output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_snapshot!("run_languages", stdout);
+}
+
+#[test]
+fn snapshot_markers() {
+    let mut cmd = tudu();
+    cmd.arg("tests/fixtures/markers").arg("--verbose");
+    let output = cmd.output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_snapshot!("run_markers", stdout);
+}
+
+#[test]
+fn min_severity_filters_output() {
+    let mut cmd = tudu();
+    cmd.arg("tests/fixtures/markers")
+        .arg("--min-severity")
+        .arg("error");
+    let output = cmd.output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // Only the BUG marker is an error in the test config
+    assert!(stdout.contains("Scanned and found 1 TODOs"));
+}
+
+#[test]
+fn fail_on_sets_exit_code() {
+    tudu()
+        .arg("tests/fixtures/markers")
+        .arg("--fail-on")
+        .arg("error")
+        .assert()
+        .failure();
+
+    // Only a TODO (a warning) in here
+    tudu()
+        .arg("tests/fixtures/repo/app.py")
+        .arg("--fail-on")
+        .arg("error")
+        .assert()
+        .success();
+}
+
+#[test]
+fn unreadable_files_are_warnings() {
+    let output = tudu()
+        .arg("tests/fixtures/unreadable")
+        .arg("--verbose")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Tracking issue TASK-90"));
+    assert!(stdout.contains("Scanned and found 1 TODOs"));
+    assert!(stderr.contains("compiled.js': looks like a binary file"));
+    assert!(stderr.contains("Warning: 'tests/fixtures/unreadable/latin1.py' is not UTF-8 or any encoding in scan.encodings"));
+}
+
+#[test]
+fn exit_codes() {
+    tudu().arg("tests/fixtures/missing").assert().code(4);
+
+    // Run from inside the fixture so its broken `.tudu.yaml` is loaded
+    tudu()
+        .current_dir("tests/fixtures/bad-config")
+        .arg(".")
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn snapshot_encodings() {
+    // The fixture has its own `.tudu.yaml` with fallback encodings
+    let output = tudu()
+        .current_dir("tests/fixtures/encodings")
+        .arg(".")
+        .arg("--verbose")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success());
+    assert_snapshot!("run_encodings", stdout);
+}
+
+#[test]
+fn snapshot_malformed() {
+    let output = tudu().arg("tests/fixtures/malformed").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // The test config runs in validate mode, which fails on malformed TODOs
+    assert_eq!(output.status.code(), Some(1));
+    assert_snapshot!("run_malformed", stdout);
+}
+
+#[test]
+fn snapshot_references() {
+    let output = tudu()
+        .arg("tests/fixtures/references")
+        .arg("--verbose")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_snapshot!("run_references", stdout);
+}
+
+#[test]
+fn snapshot_owners() {
+    let output = tudu()
+        .arg("tests/fixtures/owners")
+        .args(["--group-by", "owner"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_snapshot!("run_owners", stdout);
+
+    let output = tudu()
+        .arg("tests/fixtures/owners")
+        .args(["--owner", "@alice"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Total: 2 TODOs"), "{}", stdout);
+}
+
+#[test]
+fn fmt() {
+    let fixture = "tests/fixtures/fmt/mixed.rs";
+    let original = std::fs::read_to_string(fixture).unwrap();
+
+    // `--check` lists what would change, and doesn't change it
+    let output = tudu()
+        .args(["fmt", "--check", "tests/fixtures/fmt"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stdout.contains("4 TODO(s) aren't in the canonical form"),
+        "{}",
+        stdout
+    );
+    assert!(stderr.contains("Left 2 TODO(s) as they are"), "{}", stderr);
+    assert_eq!(std::fs::read_to_string(fixture).unwrap(), original);
+
+    // Rewriting a copy, after which it's canonical
+    let dir = std::env::temp_dir().join(format!("tudu-fmt-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let copy = dir.join("mixed.rs");
+    std::fs::write(&copy, &original).unwrap();
+
+    tudu().arg("fmt").arg(&dir).assert().success();
+    let formatted = std::fs::read_to_string(&copy).unwrap();
+    tudu().args(["fmt", "--check"]).arg(&dir).assert().success();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_snapshot!("fmt_mixed", formatted);
+}
+
+/// Files are rewritten in the encoding they were read in, fallback encodings included.
+#[test]
+fn fmt_keeps_encodings() {
+    let dir = std::env::temp_dir().join(format!("tudu-fmt-encodings-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // Falls back to windows-1252, then UTF-16LE
+    std::fs::copy(
+        "tests/fixtures/encodings/.tudu.yaml",
+        dir.join(".tudu.yaml"),
+    )
+    .unwrap();
+    let utf16le = |text: &str| -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect()
+    };
+    let files: [(&str, Vec<u8>, Vec<u8>); 3] = [
+        (
+            "latin1.py",
+            b"# caf\xE9\r# TODO(TASK-1,bidir): gr\xFC\xDFe\r".to_vec(),
+            b"# caf\xE9\r# TODO(TASK-1, bidir): gr\xFC\xDFe\r".to_vec(),
+        ),
+        (
+            "bom.rc",
+            [
+                b"\xFF\xFE".to_vec(),
+                utf16le("// TODO TASK-2: r\u{e9}sum\u{e9}\r\n"),
+            ]
+            .concat(),
+            [
+                b"\xFF\xFE".to_vec(),
+                utf16le("// TODO(TASK-2): r\u{e9}sum\u{e9}\r\n"),
+            ]
+            .concat(),
+        ),
+        (
+            "nobom.rc",
+            utf16le("// TODO:tight\n"),
+            utf16le("// TODO: tight\n"),
+        ),
+    ];
+    for (name, original, _) in &files {
+        std::fs::write(dir.join(name), original).unwrap();
+    }
+
+    let output = tudu()
+        .current_dir(&dir)
+        .args(["fmt", "."])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stdout.contains("Rewrote 3 TODO(s) in 3 file(s)"),
+        "{}{}",
+        stdout,
+        stderr
+    );
+    for (name, _, formatted) in &files {
+        assert_eq!(
+            &std::fs::read(dir.join(name)).unwrap(),
+            formatted,
+            "{}",
+            name
+        );
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn snapshot_json() {
+    // Verbose runs print notes about the run too, which mustn't end up in the JSON
+    let output = tudu()
+        .arg("tests/fixtures/malformed")
+        .args(["--format", "json", "--verbose"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let report: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(report["version"], 1);
+    assert!(stderr.contains("Using configuration"), "{}", stderr);
+    // Malformed TODOs still fail validate mode
+    assert_eq!(output.status.code(), Some(1));
+    assert_snapshot!("run_json", stdout);
+}
+
+#[test]
+fn ndjson() {
+    let output = tudu()
+        .arg(repo_fixture())
+        .args(["--format", "ndjson", "--min-severity", "error"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    let records: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let (summary, todos) = records.split_last().unwrap();
+    assert_eq!(summary["type"], "summary");
+    assert_eq!(summary["summary"]["todos"], todos.len());
+    assert!(!todos.is_empty());
+    for todo in todos {
+        assert_eq!(todo["type"], "todo");
+        assert_eq!(todo["version"], 1);
+        assert_eq!(todo["severity"], "error");
+    }
+}
+
+/// Streamed records come in whatever order files are scanned, but they're the same TODOs that
+/// aren't streamed.
+#[test]
+fn ndjson_streams_every_todo() {
+    let output = tudu()
+        .arg(repo_fixture())
+        .args(["--format", "ndjson"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut streamed: Vec<String> = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|record| record["type"] == "todo")
+        .map(|mut record| {
+            let record = record.as_object_mut().unwrap();
+            record.remove("type");
+            record.remove("version");
+            serde_json::to_string(record).unwrap()
+        })
+        .collect();
+
+    let output = tudu()
+        .arg(repo_fixture())
+        .args(["--format", "json"])
+        .output()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mut reported: Vec<String> = report["todos"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|todo| serde_json::to_string(todo).unwrap())
+        .collect();
+
+    streamed.sort();
+    reported.sort();
+    assert!(!streamed.is_empty());
+    assert_eq!(streamed, reported);
+}
+
+#[test]
+fn output_stops_quietly_when_the_reader_goes_away() {
+    let fixture = repo_fixture();
+    let fixture = fixture.to_str().unwrap();
+    for args in [
+        vec![fixture],
+        vec![fixture, "--verbose"],
+        vec![fixture, "--format", "ndjson"],
+        vec![fixture, "--format", "json"],
+        vec!["fmt", fixture, "--check"],
+    ] {
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("tudu"))
+            .args(&args)
+            .env("NOTION_TOKEN", "")
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        // Like `| head -0`: nothing is read, so the first write fails
+        drop(child.stdout.take());
+        let output = child.wait_with_output().unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert!(!stderr.contains("panicked"), "{:?}: {}", args, stderr);
+        assert_eq!(output.status.code(), Some(0), "{:?}", args);
+    }
+}
+
+/// Without a token, references can't be checked, but everything else still is.
+#[test]
+fn without_a_provider_token() {
+    for format in ["standard", "json"] {
+        let output = Command::cargo_bin("tudu")
+            .unwrap()
+            .env_remove("NOTION_TOKEN")
+            .arg(repo_fixture().join("main.js"))
+            .args(["--format", format])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        // 1 for the malformed TODOs, not 3 for the provider
+        assert_eq!(output.status.code(), Some(1), "{}", stderr);
+        assert!(stdout.contains("main.js"), "{}", stdout);
+        assert!(
+            stderr.contains("so its issues weren't checked"),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn snapshot_sarif() {
+    let output = tudu()
+        .arg("tests/fixtures/malformed")
+        .args(["--format", "sarif"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    let log: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(log["version"], "2.1.0");
+    assert_snapshot!("run_sarif", stdout);
+}
+
+#[test]
+fn snapshot_github() {
+    let summary = std::env::temp_dir().join(format!("tudu-summary-{}.md", std::process::id()));
+    std::fs::write(&summary, "Earlier step\n").unwrap();
+
+    let output = tudu()
+        .arg("tests/fixtures/malformed")
+        .args(["--format", "github"])
+        .env("GITHUB_STEP_SUMMARY", &summary)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let markdown = std::fs::read_to_string(&summary).unwrap();
+    std::fs::remove_file(&summary).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stdout.lines().all(
+            |line| line.starts_with("::error file=tests/fixtures/malformed/")
+                || line.starts_with("::warning title=Issues not checked::")
+        ),
+        "{}",
+        stdout
+    );
+    // Added to what earlier steps wrote
+    assert!(
+        markdown.starts_with("Earlier step\n## tudu\n"),
+        "{}",
+        markdown
+    );
+    assert_snapshot!("run_github", stdout);
+    assert_snapshot!("run_github_summary", markdown);
+}
@@ -0,0 +1 @@
+// TODO: never scanned
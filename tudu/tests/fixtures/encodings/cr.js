@@ -0,0 +1 @@
+const a = 1;// TODO(TASK-104): classic Mac line endingsconst b = 2;// FIXME: line five
\ No newline at end of file
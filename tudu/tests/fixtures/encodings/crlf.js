@@ -0,0 +1,6 @@
+const a = 1;
+
+// TODO(TASK-103): windows line endings
+const b = 2;
+/* FIXME: block
+   comment */
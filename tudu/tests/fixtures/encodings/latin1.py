@@ -0,0 +1,5 @@
+# Gre aus Kln
+
+def greet():
+    # TODO(TASK-102): use the user's locale
+    return "Gre"
@@ -0,0 +1,10 @@
+// TODO(alice): already canonical
+// TODO JIRA-1: legacy ID
+// FIXME - handle the empty case
+/* TODO(TASK-1,bidir): sync both ways */
+fn main() {
+    // TODO(TASK-2, labels=net, retries, bidir=true): indented
+    //   and continued on the next line
+    /// TODO(some words): left alone
+}
+// TODO(TASK-3: unclosed
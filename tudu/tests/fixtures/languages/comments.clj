@@ -0,0 +1,2 @@
+;; TODO(TASK-93): Clojure comment
+(def s "; TODO: inside a string")
@@ -0,0 +1,2 @@
+% TODO(TASK-94): Erlang comment
+X = "% TODO: inside a string".
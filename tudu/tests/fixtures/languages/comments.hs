@@ -0,0 +1,2 @@
+f x' = x' -- TODO(TASK-92): Haskell line comment
+{- FIXME: Haskell {- nested -} block -}
@@ -0,0 +1,6 @@
+const App = () => (
+  <div>
+    {/* TODO(TASK-98): JSX comment */}
+    <p>{"// TODO: inside a string"}</p>
+  </div>
+);
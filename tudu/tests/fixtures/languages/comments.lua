@@ -0,0 +1,2 @@
+--[[ TODO(TASK-91): Lua block comment ]]
+local s = "-- TODO: not a comment" -- FIXME: Lua line comment
@@ -0,0 +1 @@
+let c = '"' (* TODO(TASK-97): OCaml comment *)
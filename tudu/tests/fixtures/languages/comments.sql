@@ -0,0 +1,2 @@
+-- TODO(TASK-90): real SQL comment
+SELECT '-- TODO: inside a string' FROM notes; /* FIXME: block comment */
@@ -0,0 +1,10 @@
+def retry(task):
+    """Run a task until it succeeds.
+
+    TODO(TASK-21): give up after a configurable number
+    of attempts instead of looping forever
+    """
+    # TODO: log each attempt
+    #   with the exception that caused it
+    while True:
+        pass
@@ -0,0 +1,5 @@
+// TODO(TASK-50): real C++ comment
+auto raw = R"sql(
+  -- // TODO: inside a raw string
+)sql";
+char c = '/'; /* TODO(TASK-51): after a char literal */
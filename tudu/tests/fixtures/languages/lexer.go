@@ -0,0 +1,7 @@
+package main
+
+// TODO(TASK-40): real Go comment
+var raw = `
+// TODO: inside a raw string
+`
+var r = '"' // FIXME: after a rune
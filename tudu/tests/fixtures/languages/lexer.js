@@ -0,0 +1,4 @@
+// TODO(TASK-30): real JavaScript comment
+const html = '<!-- TODO: inside a string -->';
+const url = `https://example.com/#TODO ${html}`;
+const x = "// TODO: not a comment"; /* TODO(TASK-31): after a string */
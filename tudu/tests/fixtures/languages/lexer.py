@@ -0,0 +1,6 @@
+# TODO(TASK-20): real Python comment
+query = "SELECT '#TODO' FROM notes"  # FIXME: trailing comment
+template = """
+# TODO: inside a triple-quoted string
+"""
+path = 'a#TODO'
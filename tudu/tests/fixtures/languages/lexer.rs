@@ -0,0 +1,8 @@
+// TODO(TASK-10): real Rust comment
+fn main() {
+    let url = "https://example.com/#TODO";
+    let raw = r#"// TODO: inside a raw string"#;
+    let c = '"'; // TODO(TASK-11): after a char literal
+    let s: &'static str = "/* TODO: not a block comment */";
+    /* FIXME: nested /* block */ comment */
+}
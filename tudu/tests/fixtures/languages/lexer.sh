@@ -0,0 +1,5 @@
+#!/usr/bin/env bash
+# TODO(TASK-60): real shell comment
+echo "# TODO: inside a string"
+echo ${#args[@]} # FIXME: count arguments properly
+echo 'single # TODO: quoted'
@@ -0,0 +1,2 @@
+# TODO(TASK-99): configured Terraform comment
+resource "a" "b" {} // FIXME: configured line comment
@@ -0,0 +1,10 @@
+function main() {
+  foo(x); // TODO: parens before the marker aren't the reference
+  bar(1, 2); // TODO(TASK-1): still tracked
+
+  // TODO(): missing ref
+  // TODO(TASK-2: never closed
+  // FIXME(BUG-3, due=, =high): attributes without values or keys
+  // TODO(TASK-4, asignee=bob, due=2025-02-30): a typo and a day that does not exist
+  // TODO(alice): a person, which is fine
+}
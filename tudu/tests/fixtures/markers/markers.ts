@@ -0,0 +1,10 @@
+// HACK: work around the upstream parser bug
+// XXX: this breaks on leap years
+// BUG(BUG-12): off-by-one in pagination
+// NOTE: kept for backwards compatibility
+// OPTIMIZE: cache the compiled template
+// TBD: which queue to use
+/**
+ * @todo validate the payload
+ */
+// NOTES are not markers, and neither is HACKY code
@@ -0,0 +1,7 @@
+# TODO(alice): retry failed jobs
+def run(job):
+    # TODO(@bob, TASK-3): blocked on the scheduler API
+    # FIXME(team-infra): flaky on CI
+    # TODO: nobody owns this one
+    # TODO(alice, bidir): log how long each job took
+    return job()
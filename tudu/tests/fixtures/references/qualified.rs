@@ -0,0 +1,11 @@
+// TODO(gh:michaelfromorg/tudu#12): support GitHub issues
+// TODO(gl:platform/infra/deploy#77, bidir): support GitLab issues
+// TODO(jira:PROJ-456): support Jira issues
+// TODO(nt:25dc4188fa0e806c9dd1f4e7327751c5): link a Notion page directly
+// TODO(TASK-12): shorthand IDs still go to Notion
+// TODO(TASK-13, status="In Progress", labels=parser, lexer, bidir): quoted values and lists
+// TODO(TASK-14, BUG-7, jira:PROJ-9): blocked on all three
+// TODO JIRA-123: the legacy form
+// TODO: see https://github.com/michaelfromorg/tudu/issues/7
+// TODO(gh:tudu#12): missing the owner
+fn main() {}
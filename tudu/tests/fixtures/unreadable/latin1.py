@@ -0,0 +1,2 @@
+# Caf au lait
+# TODO: Latin-1, not UTF-8
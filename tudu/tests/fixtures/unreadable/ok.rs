@@ -0,0 +1,2 @@
+fn main() {}
+// TODO(TASK-90): still found next to unreadable files